@@ -0,0 +1,11 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+ALTER TABLE post ADD COLUMN IF NOT EXISTS search_vector TSVECTOR GENERATED ALWAYS AS (
+  setweight(to_tsvector('french', title), 'A') ||
+  setweight(to_tsvector('english', title), 'A') ||
+  setweight(to_tsvector('french', content), 'B') ||
+  setweight(to_tsvector('english', content), 'B')
+) STORED;
+
+CREATE INDEX IF NOT EXISTS post_search_vector_idx ON post USING GIN (search_vector);
+CREATE INDEX IF NOT EXISTS account_username_trgm_idx ON account USING GIN (username gin_trgm_ops);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS post_tag (
+  post_id BIGINT NOT NULL REFERENCES post(id) ON DELETE CASCADE,
+  tag VARCHAR(50) NOT NULL,
+  PRIMARY KEY (post_id, tag)
+);
+
+CREATE INDEX IF NOT EXISTS post_tag_tag_idx ON post_tag (tag);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS notification (
+  id BIGSERIAL PRIMARY KEY,
+  recipient_id BIGINT NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+  kind VARCHAR(50) NOT NULL,
+  content JSONB NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS notification_recipient_id_idx ON notification (recipient_id, id);
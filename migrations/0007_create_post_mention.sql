@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS post_mention (
+  post_id BIGINT NOT NULL REFERENCES post(id) ON DELETE CASCADE,
+  account_id BIGINT NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+  PRIMARY KEY (post_id, account_id)
+);
+
+CREATE INDEX IF NOT EXISTS post_mention_account_id_idx ON post_mention (account_id);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS reaction (
+  post_id BIGINT NOT NULL REFERENCES post(id) ON DELETE CASCADE,
+  account_id BIGINT NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+  kind VARCHAR(20) NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (post_id, account_id)
+);
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS comment (
+  id BIGSERIAL PRIMARY KEY,
+  post_id BIGINT NOT NULL REFERENCES post(id) ON DELETE CASCADE,
+  author_id BIGINT NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+  parent_id BIGINT REFERENCES comment(id) ON DELETE CASCADE,
+  content VARCHAR(1000) NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS comment_post_id_idx ON comment (post_id, id);
+CREATE INDEX IF NOT EXISTS comment_parent_id_idx ON comment (parent_id, id);
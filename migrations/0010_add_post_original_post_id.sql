@@ -0,0 +1,5 @@
+ALTER TABLE post ADD COLUMN IF NOT EXISTS original_post_id BIGINT REFERENCES post(id) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS post_original_post_id_idx ON post (original_post_id);
+-- An user can only repost a post once, quotes (reposts with a title and a content) are not limited
+CREATE UNIQUE INDEX IF NOT EXISTS post_repost_idx ON post (author_id, original_post_id) WHERE title = '' AND content = '';
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS bookmark (
+  id BIGSERIAL PRIMARY KEY,
+  account_id BIGINT NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+  post_id BIGINT NOT NULL REFERENCES post(id) ON DELETE CASCADE,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  UNIQUE (account_id, post_id)
+);
+
+CREATE INDEX IF NOT EXISTS bookmark_account_id_idx ON bookmark (account_id, id);
@@ -0,0 +1,2 @@
+-- 0 = public, 1 = unlisted, 2 = followers only
+ALTER TABLE post ADD COLUMN IF NOT EXISTS visibility INT NOT NULL DEFAULT 0;
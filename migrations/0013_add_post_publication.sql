@@ -0,0 +1,6 @@
+-- Drafts are never published automatically, scheduled posts are published by the server at `publish_at`
+ALTER TABLE post ADD COLUMN IF NOT EXISTS published BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE post ADD COLUMN IF NOT EXISTS publish_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS post_publish_at_idx ON post (publish_at) WHERE NOT published;
+CREATE INDEX IF NOT EXISTS post_drafts_idx ON post (author_id, id) WHERE NOT published;
@@ -0,0 +1,17 @@
+-- Media are uploaded before the post they are attached to, `post_id` is null until then
+CREATE TABLE IF NOT EXISTS media (
+  id BIGSERIAL PRIMARY KEY,
+  owner_id BIGINT NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+  post_id BIGINT REFERENCES post(id) ON DELETE SET NULL,
+  position INT NOT NULL DEFAULT 0,
+  content_type VARCHAR(50) NOT NULL,
+  width INT NOT NULL,
+  height INT NOT NULL,
+  storage_key VARCHAR(100) NOT NULL,
+  url VARCHAR(500) NOT NULL,
+  thumbnail_storage_key VARCHAR(100) NOT NULL,
+  thumbnail_url VARCHAR(500) NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS media_post_id_idx ON media (post_id, position);
@@ -0,0 +1,5 @@
+-- The storage keys are kept to delete the previous image when it is replaced
+ALTER TABLE account ADD COLUMN IF NOT EXISTS avatar_storage_key VARCHAR(100);
+ALTER TABLE account ADD COLUMN IF NOT EXISTS avatar_url VARCHAR(500);
+ALTER TABLE account ADD COLUMN IF NOT EXISTS banner_storage_key VARCHAR(100);
+ALTER TABLE account ADD COLUMN IF NOT EXISTS banner_url VARCHAR(500);
@@ -0,0 +1,32 @@
+CREATE TABLE IF NOT EXISTS poll (
+  post_id BIGINT PRIMARY KEY REFERENCES post(id) ON DELETE CASCADE,
+  multiple_choice BOOLEAN NOT NULL,
+  expires_at TIMESTAMPTZ NOT NULL,
+  -- Set by the server once the voters have been notified of the results
+  closed BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX IF NOT EXISTS poll_expires_at_idx ON poll (expires_at) WHERE NOT closed;
+
+CREATE TABLE IF NOT EXISTS poll_option (
+  id BIGSERIAL PRIMARY KEY,
+  post_id BIGINT NOT NULL REFERENCES poll(post_id) ON DELETE CASCADE,
+  position INT NOT NULL,
+  text VARCHAR(100) NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS poll_option_post_id_idx ON poll_option (post_id, position);
+
+-- An user can only vote once to a poll, even for a multiple choice poll
+CREATE TABLE IF NOT EXISTS poll_voter (
+  post_id BIGINT NOT NULL REFERENCES poll(post_id) ON DELETE CASCADE,
+  account_id BIGINT NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (post_id, account_id)
+);
+
+CREATE TABLE IF NOT EXISTS poll_vote (
+  option_id BIGINT NOT NULL REFERENCES poll_option(id) ON DELETE CASCADE,
+  account_id BIGINT NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+  PRIMARY KEY (option_id, account_id)
+);
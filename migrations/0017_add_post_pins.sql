@@ -0,0 +1,6 @@
+-- Posts pinned by their author on their profile, and site-wide announcements pinned by the moderators
+ALTER TABLE post ADD COLUMN IF NOT EXISTS pinned_at TIMESTAMPTZ;
+ALTER TABLE post ADD COLUMN IF NOT EXISTS announced_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS post_pinned_idx ON post (author_id) WHERE pinned_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS post_announced_idx ON post (announced_at) WHERE announced_at IS NOT NULL;
@@ -0,0 +1,4 @@
+-- Notifications are kept in the inbox of their recipient until they are deleted with their account
+ALTER TABLE notification ADD COLUMN IF NOT EXISTS read BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS notification_unread_idx ON notification (recipient_id) WHERE NOT read;
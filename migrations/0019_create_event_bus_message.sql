@@ -0,0 +1,6 @@
+-- Messages of the Postgres event bus too big to be sent with NOTIFY, they are deleted after a minute
+CREATE TABLE IF NOT EXISTS event_bus_message (
+  id BIGSERIAL PRIMARY KEY,
+  content TEXT NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
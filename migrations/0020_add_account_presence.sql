@@ -0,0 +1,3 @@
+-- Set when the last connection of an user is closed, the presence of an user can be hidden from the others
+ALTER TABLE account ADD COLUMN IF NOT EXISTS last_seen_at TIMESTAMPTZ;
+ALTER TABLE account ADD COLUMN IF NOT EXISTS presence_hidden BOOLEAN NOT NULL DEFAULT FALSE;
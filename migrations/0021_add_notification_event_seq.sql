@@ -0,0 +1,14 @@
+-- The notifications of an user are numbered by a sequence of their recipient, incremented under the lock of its row
+-- The numbers are committed in order, so a reconnecting client never misses an event with a lower number
+ALTER TABLE account ADD COLUMN IF NOT EXISTS last_event_seq BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE notification ADD COLUMN IF NOT EXISTS event_seq BIGINT;
+
+UPDATE notification SET event_seq = numbered.event_seq
+FROM (SELECT id, ROW_NUMBER() OVER (PARTITION BY recipient_id ORDER BY id) AS event_seq FROM notification) AS numbered
+WHERE notification.id = numbered.id AND notification.event_seq IS NULL;
+
+UPDATE account SET last_event_seq = (SELECT COALESCE(MAX(event_seq), 0) FROM notification WHERE recipient_id = account.id);
+
+ALTER TABLE notification ALTER COLUMN event_seq SET NOT NULL;
+
+CREATE UNIQUE INDEX IF NOT EXISTS notification_recipient_event_seq_idx ON notification (recipient_id, event_seq);
@@ -0,0 +1,3 @@
+-- The replies of a deleted comment are kept as top-level comments, instead of being deleted with it
+ALTER TABLE comment DROP CONSTRAINT IF EXISTS comment_parent_id_fkey;
+ALTER TABLE comment ADD CONSTRAINT comment_parent_id_fkey FOREIGN KEY (parent_id) REFERENCES comment(id) ON DELETE SET NULL;
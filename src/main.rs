@@ -6,36 +6,69 @@ mod routes;
 mod utils;
 
 use axum::{
+    extract::DefaultBodyLimit,
     middleware as axum_middleware,
-    routing::{get, post},
+    routing::{get, patch, post, put},
 };
 use axum::{Extension, Router};
 use dotenvy::dotenv;
 use libaes::Cipher;
+use routes::events_route::events_route;
 use routes::follow_user_route::follow_user_route;
-use routes::ws_route::ws_route;
+use routes::ws_route::{ws_metrics_route, ws_route};
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use std::env::var;
 use std::net::SocketAddr;
-use std::sync::atomic::{AtomicI64, AtomicUsize};
+use std::path::PathBuf;
+use std::sync::atomic::AtomicI64;
 use std::sync::Arc;
+use std::time::Duration;
 
 use tracing::{info, warn};
 
-use crate::routes::{get_posts::get_posts_route, publish_post::publish_post_route};
+use crate::routes::{
+    get_post::get_post_route, get_posts::get_posts_route, get_tag_posts::get_tag_posts_route,
+    get_timeline::get_timeline_route, get_trending_tags::get_trending_tags_route,
+    get_user_posts::get_user_posts_route, publish_post::publish_post_route,
+    repost_route::repost_route,
+};
+use crate::utils::close_expired_polls::close_expired_polls;
 use crate::utils::delete_not_activated_expired_accounts::delete_not_activated_expired_accounts;
-use crate::utils::real_time_event_management::EventTracker;
+use crate::utils::event_bus::{EventBus, MemoryEventBus, PostgresEventBus};
+use crate::utils::media::MAX_MEDIA_SIZE;
+use crate::utils::media_storage::{LocalMediaStorage, MediaStorage};
+use crate::utils::publish_scheduled_posts::publish_scheduled_posts;
 use crate::utils::real_time_event_management::Users;
+use crate::utils::real_time_event_management::{EventTracker, HeartbeatConfig};
 use hyper::header;
-use hyper::header::HeaderValue;
+use hyper::header::{HeaderName, HeaderValue};
 use hyper::http::Method;
 use lettre::{transport::smtp::authentication::Credentials, SmtpTransport};
 use middleware::logger::logger;
 use routes::a2f_login_route::a2f_login_route;
+use routes::bookmark_route::{add_bookmark_route, get_bookmarks_route, remove_bookmark_route};
+use routes::comment_route::{
+    delete_comment_route, edit_comment_route, get_comments_route, publish_comment_route,
+};
+use routes::draft_route::{edit_draft_route, get_drafts_route};
 use routes::email_confirm_route::email_confirm_route;
 use routes::login_route::login_route;
+use routes::media_route::{get_media_file_route, upload_media_route};
+use routes::notification_route::{
+    get_notifications_route, get_unread_notifications_count_route, read_notifications_route,
+};
 use routes::ok_route::ok_route;
+use routes::pin_route::{
+    pin_announcement_route, pin_post_route, unpin_announcement_route, unpin_post_route,
+};
+use routes::poll_route::vote_route;
+use routes::post_reaction_route::{add_reaction_route, remove_reaction_route};
+use routes::presence_route::{get_presence_route, set_presence_visibility_route};
+use routes::profile_image_route::{
+    delete_avatar_route, delete_banner_route, upload_avatar_route, upload_banner_route,
+};
 use routes::register_route::register_route;
+use routes::search_route::search_route;
 use tower_http::cors::CorsLayer;
 
 /// The global state of the app
@@ -43,11 +76,14 @@ pub struct AppState {
     pool: PgPool,
     smtp_client: SmtpTransport,
     cipher: Cipher,
+    media_storage: Box<dyn MediaStorage>,
+    heartbeat: HeartbeatConfig,
 }
 
 const FRONT_URL: &str = env!("FRONT_URL");
+/// The URL of the `/media/files` route
+const MEDIA_URL: &str = env!("MEDIA_URL");
 static NEXT_NOT_CONNECTED_USER_ID: AtomicI64 = AtomicI64::new(-1);
-static CONNECTED_USERS_COUNT: AtomicUsize = AtomicUsize::new(0);
 
 #[tokio::main]
 async fn main() {
@@ -66,45 +102,43 @@ async fn main() {
         return;
     };
 
+    let Some(media_storage) = setup_media_storage().await else {
+        return;
+    };
+
+    let Some(heartbeat) = setup_heartbeat() else {
+        return;
+    };
+
     let app_state = Arc::new(AppState {
         pool,
         smtp_client,
         cipher,
+        media_storage,
+        heartbeat,
     });
 
-    let front_url = match FRONT_URL.parse::<HeaderValue>() {
-        Ok(url) => url,
-        Err(e) => {
-            warn!("FRONT_URL is an invalid URL : {e}");
-            return;
-        }
+    let Some(cors) = setup_cors() else {
+        return;
     };
 
-    let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
-        .allow_origin(front_url)
-        .allow_headers(vec![
-            header::ACCEPT,
-            header::ACCEPT_LANGUAGE,
-            header::CONTENT_TYPE,
-            header::AUTHORIZATION, // Add other allowed headers here
-        ])
-        .allow_credentials(true);
+    let Some(event_bus) = setup_event_bus(&app_state.pool).await else {
+        return;
+    };
 
-    let router = Router::new()
-        .route("/", get(ok_route))
-        .route("/register", post(register_route))
-        .route("/register/email_confirm", post(email_confirm_route))
-        .route("/login", post(login_route))
-        .route("/login/a2f", post(a2f_login_route))
-        .route("/ws", get(ws_route))
-        .route("/@:username/follow", post(follow_user_route))
-        .route("/posts/new", post(publish_post_route))
-        .route("/posts", get(get_posts_route))
+    let Some(users_count_interval) = get_seconds_env_var("USERS_COUNT_BROADCAST_INTERVAL", 1)
+    else {
+        return;
+    };
+
+    let users = Users::default();
+    let event_tracker = EventTracker::new(event_bus, app_state.pool.clone(), users_count_interval);
+
+    let router = setup_router()
         .layer(cors)
         .layer(axum_middleware::from_fn(logger))
-        .layer(Extension(Users::default()))
-        .layer(Extension(EventTracker::default()))
+        .layer(Extension(users.clone()))
+        .layer(Extension(event_tracker.clone()))
         .with_state(app_state.clone());
 
     let serve_router = axum::Server::bind(&"0.0.0.0:8080".parse().unwrap())
@@ -114,10 +148,124 @@ async fn main() {
         () = delete_not_activated_expired_accounts(&app_state) => {
             warn!("This should never happen");
         },
+        () = publish_scheduled_posts(&app_state, &users, &event_tracker) => {
+            warn!("This should never happen");
+        },
+        () = close_expired_polls(&app_state, &event_tracker) => {
+            warn!("This should never happen");
+        },
+        () = event_tracker.relay_bus_messages(&users) => {
+            warn!("This should never happen");
+        },
         _ = serve_router => {}
     };
 }
 
+fn setup_router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(ok_route))
+        .route("/register", post(register_route))
+        .route("/register/email_confirm", post(email_confirm_route))
+        .route("/login", post(login_route))
+        .route("/login/a2f", post(a2f_login_route))
+        .route("/ws", get(ws_route))
+        .route("/ws/metrics", get(ws_metrics_route))
+        .route("/events", get(events_route))
+        .route("/@:username/follow", post(follow_user_route))
+        .route("/@:username/posts", get(get_user_posts_route))
+        .route("/@:username/presence", get(get_presence_route))
+        .route("/posts/new", post(publish_post_route))
+        .route("/posts", get(get_posts_route))
+        .route("/posts/:id", get(get_post_route).patch(edit_draft_route))
+        .route("/drafts", get(get_drafts_route))
+        .route("/posts/:id/repost", post(repost_route))
+        .route("/posts/:id/poll/vote", post(vote_route))
+        .route(
+            "/posts/:id/pin",
+            put(pin_post_route).delete(unpin_post_route),
+        )
+        .route(
+            "/posts/:id/announcement",
+            put(pin_announcement_route).delete(unpin_announcement_route),
+        )
+        .route(
+            "/posts/:id/bookmark",
+            put(add_bookmark_route).delete(remove_bookmark_route),
+        )
+        .route("/bookmarks", get(get_bookmarks_route))
+        .route(
+            "/media",
+            //Leave room for the other parts of the multipart body
+            post(upload_media_route).layer(DefaultBodyLimit::max(MAX_MEDIA_SIZE + 64 * 1024)),
+        )
+        .route("/media/files/:key", get(get_media_file_route))
+        .route(
+            "/account/avatar",
+            put(upload_avatar_route)
+                .delete(delete_avatar_route)
+                .layer(DefaultBodyLimit::max(MAX_MEDIA_SIZE + 64 * 1024)),
+        )
+        .route(
+            "/account/banner",
+            put(upload_banner_route)
+                .delete(delete_banner_route)
+                .layer(DefaultBodyLimit::max(MAX_MEDIA_SIZE + 64 * 1024)),
+        )
+        .route("/account/presence", put(set_presence_visibility_route))
+        .route("/timeline", get(get_timeline_route))
+        .route("/notifications", get(get_notifications_route))
+        .route(
+            "/notifications/unread_count",
+            get(get_unread_notifications_count_route),
+        )
+        .route("/notifications/read", post(read_notifications_route))
+        .route(
+            "/posts/:id/reactions/:kind",
+            put(add_reaction_route).delete(remove_reaction_route),
+        )
+        .route(
+            "/posts/:id/comments",
+            get(get_comments_route).post(publish_comment_route),
+        )
+        .route(
+            "/posts/:id/comments/:comment_id",
+            patch(edit_comment_route).delete(delete_comment_route),
+        )
+        .route("/search", get(search_route))
+        .route("/tags/trending", get(get_trending_tags_route))
+        .route("/tags/:tag/posts", get(get_tag_posts_route))
+}
+
+fn setup_cors() -> Option<CorsLayer> {
+    let front_url = match FRONT_URL.parse::<HeaderValue>() {
+        Ok(url) => url,
+        Err(e) => {
+            warn!("FRONT_URL is an invalid URL : {e}");
+            return None;
+        }
+    };
+
+    let cors = CorsLayer::new()
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_origin(front_url)
+        .allow_headers(vec![
+            header::ACCEPT,
+            header::ACCEPT_LANGUAGE,
+            header::CONTENT_TYPE,
+            header::AUTHORIZATION,
+            HeaderName::from_static("last-event-id"), // Add other allowed headers here
+        ])
+        .allow_credentials(true);
+
+    Some(cors)
+}
+
 async fn setup_pool() -> Option<PgPool> {
     let database_url = match var("DATABASE_URL") {
         Ok(url) => url,
@@ -196,3 +344,60 @@ fn setup_cipher() -> Option<Cipher> {
 
     Some(Cipher::new_256(&secret_key))
 }
+
+async fn setup_media_storage() -> Option<Box<dyn MediaStorage>> {
+    let directory = match var("MEDIA_DIRECTORY") {
+        Ok(directory) => PathBuf::from(directory),
+        Err(e) => {
+            warn!("Error getting MEDIA_DIRECTORY env variable : {e}");
+            return None;
+        }
+    };
+
+    if let Err(e) = tokio::fs::create_dir_all(&directory).await {
+        warn!("Error creating the media directory : {e}");
+        return None;
+    }
+
+    Some(Box::new(LocalMediaStorage::new(directory, MEDIA_URL)))
+}
+
+/// Get the bus that sends the real-time events to the other instances of the API, with the `EVENT_BUS` env variable
+async fn setup_event_bus(pool: &PgPool) -> Option<Arc<dyn EventBus>> {
+    match var("EVENT_BUS").as_deref() {
+        Err(_) | Ok("memory") => Some(Arc::new(MemoryEventBus)),
+        Ok("postgres") => match PostgresEventBus::new(pool.clone()).await {
+            Ok(event_bus) => Some(Arc::new(event_bus)),
+            Err(e) => {
+                warn!("Error listening to the Postgres event bus : {e}");
+                None
+            }
+        },
+        Ok(event_bus) => {
+            warn!("Unknown event bus `{event_bus}`, it must be `memory` or `postgres`");
+            None
+        }
+    }
+}
+
+/// Read a duration in seconds from an optional env variable
+fn get_seconds_env_var(name: &str, default: u64) -> Option<Duration> {
+    let Ok(seconds) = var(name) else {
+        return Some(Duration::from_secs(default));
+    };
+
+    match seconds.parse() {
+        Ok(seconds) if seconds > 0 => Some(Duration::from_secs(seconds)),
+        _ => {
+            warn!("{name} must be a number of seconds greater than 0");
+            None
+        }
+    }
+}
+
+fn setup_heartbeat() -> Option<HeartbeatConfig> {
+    Some(HeartbeatConfig {
+        ping_interval: get_seconds_env_var("WS_PING_INTERVAL", 30)?,
+        pong_timeout: get_seconds_env_var("WS_PONG_TIMEOUT", 10)?,
+    })
+}
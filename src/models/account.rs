@@ -1,5 +1,7 @@
 use time::OffsetDateTime;
 
+use crate::utils::avatar::get_avatar_url;
+
 pub struct Account {
     pub id: i64,
     pub username: String,
@@ -17,21 +19,63 @@ pub struct Account {
     pub permission: i64,
 }
 
+#[derive(serde::Serialize)]
 pub struct PublicAccount {
     pub id: i64,
     pub username: String,
     pub biography: String,
     pub created_at: OffsetDateTime,
     pub permission: AccountPermission,
+    pub avatar_url: String,
+    pub banner_url: Option<String>,
 }
 
-#[derive(serde::Serialize)]
+/// An account with only its public fields, as selected from the database
+pub struct PublicAccountRow {
+    pub id: i64,
+    pub username: String,
+    pub biography: String,
+    pub created_at: OffsetDateTime,
+    pub permission: AccountPermission,
+    pub avatar_url: Option<String>,
+    pub banner_url: Option<String>,
+}
+
+impl From<PublicAccountRow> for PublicAccount {
+    fn from(account: PublicAccountRow) -> Self {
+        PublicAccount {
+            id: account.id,
+            username: account.username,
+            biography: account.biography,
+            created_at: account.created_at,
+            permission: account.permission,
+            avatar_url: get_avatar_url(account.id, account.avatar_url),
+            banner_url: account.banner_url,
+        }
+    }
+}
+
+#[derive(serde::Serialize, Clone, Copy)]
 pub enum AccountPermission {
     User = 0,
     Moderator = 1,
     Administrator = 2,
 }
 
+impl AccountPermission {
+    /// Moderators and administrators can moderate the content of the other users
+    pub fn is_moderator(self) -> bool {
+        matches!(
+            self,
+            AccountPermission::Moderator | AccountPermission::Administrator
+        )
+    }
+
+    pub fn is_administrator(self) -> bool {
+        matches!(self, AccountPermission::Administrator)
+    }
+}
+
 impl From<i32> for AccountPermission {
     fn from(value: i32) -> Self {
         match value {
@@ -0,0 +1,54 @@
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use super::{account::AccountPermission, post::PublicPostAuthor};
+use crate::utils::avatar::get_avatar_url;
+
+#[derive(Serialize)]
+pub struct PublicComment {
+    pub id: i64,
+    pub post_id: i64,
+    pub parent_id: Option<i64>,
+    pub author: PublicPostAuthor,
+    pub content: String,
+    pub replies_count: i64,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+/// A comment joined with its author, as selected from the database
+pub struct CommentWithAuthor {
+    pub id: i64,
+    pub post_id: i64,
+    pub parent_id: Option<i64>,
+    pub content: String,
+    pub replies_count: i64,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+    pub author_id: i64,
+    pub author_username: String,
+    pub author_permission: AccountPermission,
+    pub author_avatar_url: Option<String>,
+    pub author_banner_url: Option<String>,
+}
+
+impl From<CommentWithAuthor> for PublicComment {
+    fn from(comment: CommentWithAuthor) -> Self {
+        PublicComment {
+            id: comment.id,
+            post_id: comment.post_id,
+            parent_id: comment.parent_id,
+            author: PublicPostAuthor {
+                id: comment.author_id,
+                username: comment.author_username,
+                permission: comment.author_permission,
+                avatar_url: get_avatar_url(comment.author_id, comment.author_avatar_url),
+                banner_url: comment.author_banner_url,
+            },
+            content: comment.content,
+            replies_count: comment.replies_count,
+            created_at: comment.created_at,
+            updated_at: comment.updated_at,
+        }
+    }
+}
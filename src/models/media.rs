@@ -0,0 +1,11 @@
+use serde::Serialize;
+
+#[derive(Serialize, Clone)]
+pub struct PublicMedia {
+    pub id: i64,
+    pub content_type: String,
+    pub width: i32,
+    pub height: i32,
+    pub url: String,
+    pub thumbnail_url: String,
+}
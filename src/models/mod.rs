@@ -1,2 +1,8 @@
 pub mod account;
+pub mod comment;
+pub mod media;
+pub mod notification;
+pub mod poll;
 pub mod post;
+pub mod presence;
+pub mod reaction;
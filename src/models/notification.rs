@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// A notification of the inbox of an user
+#[derive(Serialize)]
+pub struct PublicNotification {
+    pub id: i64,
+    /// The name of the WS event the notification was sent with
+    pub kind: String,
+    /// The content of the WS event the notification was sent with
+    pub content: serde_json::Value,
+    pub read: bool,
+    pub created_at: OffsetDateTime,
+}
+
+/// The notifications to mark as read, either by their ids or all of them up to a notification
+#[derive(Deserialize)]
+pub struct ReadNotifications {
+    pub ids: Option<Vec<i64>>,
+    pub up_to: Option<i64>,
+}
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+#[derive(Deserialize)]
+pub struct NewPoll {
+    pub options: Vec<String>,
+    #[serde(default)]
+    pub multiple_choice: bool,
+    #[serde(with = "time::serde::rfc3339")]
+    pub expires_at: OffsetDateTime,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PublicPoll {
+    pub options: Vec<PublicPollOption>,
+    pub multiple_choice: bool,
+    pub expires_at: OffsetDateTime,
+    pub closed: bool,
+    /// The number of users that voted
+    pub voters_count: i64,
+    /// The ids of the options chosen by the authenticated user
+    pub my_votes: Vec<i64>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PublicPollOption {
+    pub id: i64,
+    pub text: String,
+    /// `None` until the authenticated user votes or the poll is closed
+    pub votes_count: Option<i64>,
+}
+
+/// The results of a poll, as sent to the subscribers of the poll
+#[derive(Serialize)]
+pub struct PollResults {
+    pub post_id: i64,
+    pub closed: bool,
+    pub voters_count: i64,
+    /// `None` until the poll is closed, the results are hidden from the users that haven't voted
+    pub options: Option<Vec<PollOptionResult>>,
+}
+
+#[derive(Serialize)]
+pub struct PollOptionResult {
+    pub id: i64,
+    pub votes_count: i64,
+}
@@ -1,7 +1,12 @@
+use std::collections::BTreeMap;
+
 use time::OffsetDateTime;
 
-use super::account::AccountPermission;
-use serde::Serialize;
+use super::{
+    account::AccountPermission, media::PublicMedia, poll::PublicPoll, reaction::ReactionKind,
+};
+use crate::utils::avatar::get_avatar_url;
+use serde::{Deserialize, Serialize};
 
 pub struct Post {
     pub id: i64,
@@ -12,27 +17,197 @@ pub struct Post {
     pub updated_at: OffsetDateTime,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct PublicPost {
     pub id: i64,
     pub author: PublicPostAuthor,
     pub title: String,
+    /// The Markdown source of the post
     pub content: String,
+    /// `content` rendered to sanitized HTML
+    pub content_html: String,
+    pub visibility: PostVisibility,
+    /// The media attached to the post, in their order in the post
+    pub media: Vec<PublicMedia>,
+    pub poll: Option<PublicPoll>,
+    pub mentions: Vec<PostMention>,
+    pub reactions: BTreeMap<ReactionKind, i64>,
+    pub my_reaction: Option<ReactionKind>,
+    /// Whether the authenticated user saved this post in its bookmarks
+    pub bookmarked: bool,
+    /// The post reposted or quoted by this one
+    pub original_post_id: Option<i64>,
+    /// Only one level of reposts is embedded, `original_post` is always `None` for this post
+    pub original_post: Option<Box<PublicPost>>,
+    /// Drafts and scheduled posts are only visible by their author
+    pub published: bool,
+    pub publish_at: Option<OffsetDateTime>,
+    /// Whether the post is pinned on the profile of its author
+    pub pinned: bool,
+    /// When the post was pinned by a moderator at the top of the posts, `None` if it isn't an announcement
+    pub announced_at: Option<OffsetDateTime>,
     pub created_at: OffsetDateTime,
     pub updated_at: OffsetDateTime,
 }
 
+/// When a post is published
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PostPublication {
+    /// Published right away
+    Now,
+    /// Only published when its author edits it
+    Draft,
+    /// Published by the server at the given date
+    Scheduled(OffsetDateTime),
+}
+
+impl PostPublication {
+    pub fn is_published(self) -> bool {
+        self == PostPublication::Now
+    }
+
+    pub fn publish_at(self) -> Option<OffsetDateTime> {
+        match self {
+            PostPublication::Scheduled(publish_at) => Some(publish_at),
+            PostPublication::Now | PostPublication::Draft => None,
+        }
+    }
+}
+
+/// Who can see a post, ordered from the most to the least visible
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PostVisibility {
+    /// Visible by everyone, everywhere
+    #[default]
+    Public = 0,
+    /// Visible by everyone, but only reachable by its id
+    Unlisted = 1,
+    /// Only visible by the followers of the author
+    Followers = 2,
+}
+
+impl From<i32> for PostVisibility {
+    fn from(value: i32) -> Self {
+        match value {
+            1 => PostVisibility::Unlisted,
+            2 => PostVisibility::Followers,
+            _ => PostVisibility::Public,
+        }
+    }
+}
+
+/// An user mentioned in the content of a post
+/// `start` and `end` are positions in characters in the content, `@` included
+#[derive(Serialize, Clone)]
+pub struct PostMention {
+    pub user_id: i64,
+    pub username: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A post joined with its author, as selected from the database
+pub struct PostWithAuthor {
+    pub id: i64,
+    pub title: String,
+    pub content: String,
+    pub visibility: PostVisibility,
+    pub original_post_id: Option<i64>,
+    pub published: bool,
+    pub publish_at: Option<OffsetDateTime>,
+    pub pinned: bool,
+    pub announced_at: Option<OffsetDateTime>,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+    pub author_id: i64,
+    pub author_username: String,
+    pub author_permission: AccountPermission,
+    pub author_avatar_url: Option<String>,
+    pub author_banner_url: Option<String>,
+}
+
+impl From<PostWithAuthor> for PublicPost {
+    fn from(post: PostWithAuthor) -> Self {
+        PublicPost {
+            id: post.id,
+            title: post.title,
+            content: post.content,
+            content_html: String::new(),
+            visibility: post.visibility,
+            media: Vec::new(),
+            poll: None,
+            mentions: Vec::new(),
+            reactions: BTreeMap::new(),
+            my_reaction: None,
+            bookmarked: false,
+            original_post_id: post.original_post_id,
+            original_post: None,
+            published: post.published,
+            publish_at: post.publish_at,
+            pinned: post.pinned,
+            announced_at: post.announced_at,
+            author: PublicPostAuthor {
+                id: post.author_id,
+                username: post.author_username,
+                permission: post.author_permission,
+                avatar_url: get_avatar_url(post.author_id, post.author_avatar_url),
+                banner_url: post.author_banner_url,
+            },
+            created_at: post.created_at,
+            updated_at: post.updated_at,
+        }
+    }
+}
+
+/// A post joined with its author, with only the fields needed by `NotificationPost`
+pub struct NotificationPostWithAuthor {
+    pub id: i64,
+    pub title: String,
+    pub visibility: PostVisibility,
+    pub original_post_id: Option<i64>,
+    pub created_at: OffsetDateTime,
+    pub author_id: i64,
+    pub author_username: String,
+    pub author_permission: AccountPermission,
+    pub author_avatar_url: Option<String>,
+    pub author_banner_url: Option<String>,
+}
+
+impl From<NotificationPostWithAuthor> for NotificationPost {
+    fn from(post: NotificationPostWithAuthor) -> Self {
+        NotificationPost {
+            id: post.id,
+            title: post.title,
+            visibility: post.visibility,
+            original_post_id: post.original_post_id,
+            author: PublicPostAuthor {
+                id: post.author_id,
+                username: post.author_username,
+                permission: post.author_permission,
+                avatar_url: get_avatar_url(post.author_id, post.author_avatar_url),
+                banner_url: post.author_banner_url,
+            },
+            created_at: post.created_at,
+        }
+    }
+}
+
 #[derive(Serialize)]
 pub struct NotificationPost {
     pub id: i64,
     pub author: PublicPostAuthor,
     pub title: String,
+    pub visibility: PostVisibility,
+    pub original_post_id: Option<i64>,
     pub created_at: OffsetDateTime,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct PublicPostAuthor {
     pub id: i64,
     pub username: String,
     pub permission: AccountPermission,
+    pub avatar_url: String,
+    pub banner_url: Option<String>,
 }
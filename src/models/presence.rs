@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// The presence of an user, ordered from the least to the most present
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceStatus {
+    Offline,
+    /// All the connections of the user are away
+    Away,
+    Online,
+}
+
+#[derive(Serialize)]
+pub struct PublicPresence {
+    pub status: PresenceStatus,
+    /// When the user was last connected, `None` if it is connected or was never connected
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_seen_at: Option<OffsetDateTime>,
+}
+
+#[derive(Deserialize)]
+pub struct PresenceVisibility {
+    /// Hide the presence of the user from the other users
+    pub hidden: bool,
+}
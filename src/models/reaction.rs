@@ -0,0 +1,39 @@
+use serde::Serialize;
+
+/// All the reactions an user can add to a post
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ReactionKind {
+    Like,
+    Love,
+    Laugh,
+    Wow,
+    Sad,
+    Angry,
+}
+
+impl ReactionKind {
+    /// Get a reaction from its name, as stored in the database and sent to the clients
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "like" => Some(ReactionKind::Like),
+            "love" => Some(ReactionKind::Love),
+            "laugh" => Some(ReactionKind::Laugh),
+            "wow" => Some(ReactionKind::Wow),
+            "sad" => Some(ReactionKind::Sad),
+            "angry" => Some(ReactionKind::Angry),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ReactionKind::Like => "like",
+            ReactionKind::Love => "love",
+            ReactionKind::Laugh => "laugh",
+            ReactionKind::Wow => "wow",
+            ReactionKind::Sad => "sad",
+            ReactionKind::Angry => "angry",
+        }
+    }
+}
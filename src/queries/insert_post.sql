@@ -1,6 +1,6 @@
 WITH inserted_post AS (
-    INSERT INTO post (author_id, title, content) VALUES ($1, $2, $3) RETURNING *
+    INSERT INTO post (author_id, title, content, original_post_id, visibility, published, publish_at) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *
 )
-SELECT inserted_post.id, title, inserted_post.created_at, account.id AS author_id, account.username AS author_username, account.permission AS author_permission
+SELECT inserted_post.id, title, inserted_post.visibility, inserted_post.original_post_id, inserted_post.created_at, account.id AS author_id, account.username AS author_username, account.permission AS author_permission, account.avatar_url AS author_avatar_url, account.banner_url AS author_banner_url
 FROM inserted_post
 JOIN account ON inserted_post.author_id = account.id;
@@ -0,0 +1,6 @@
+SELECT id, username, biography, created_at, permission, avatar_url, banner_url
+FROM account
+WHERE email_verified = TRUE AND is_banned = FALSE AND (username % $1 OR strpos(username, $1) > 0)
+ORDER BY similarity(username, $1) DESC, id
+LIMIT $2
+OFFSET $3;
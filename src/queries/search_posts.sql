@@ -0,0 +1,6 @@
+SELECT post.id
+FROM post, websearch_to_tsquery('french', $1) AS french_query, websearch_to_tsquery('english', $1) AS english_query
+WHERE post.published AND post.visibility = 0 AND post.search_vector @@ (french_query || english_query)
+ORDER BY ts_rank(post.search_vector, french_query || english_query) DESC, post.id DESC
+LIMIT $2
+OFFSET $3;
@@ -0,0 +1,6 @@
+SELECT comment.id, comment.post_id, comment.parent_id, comment.content, comment.created_at, comment.updated_at, account.id AS author_id, account.username AS author_username, account.permission AS author_permission, account.avatar_url AS author_avatar_url, account.banner_url AS author_banner_url, (SELECT COUNT(*) FROM comment AS reply WHERE reply.parent_id = comment.id) AS "replies_count!"
+FROM comment
+JOIN account ON comment.author_id = account.id
+WHERE comment.post_id = $1 AND comment.parent_id IS NOT DISTINCT FROM $2 AND ($3::BIGINT IS NULL OR comment.id < $3)
+ORDER BY comment.id DESC
+LIMIT $4;
@@ -1,5 +1,7 @@
-SELECT post.id, post.title, post.content, post.created_at, post.updated_at, account.id AS author_id, account.username AS author_username, account.permission AS author_permission
+SELECT post.id
 FROM post
-JOIN account ON post.author_id = account.id
+WHERE post.published AND post.visibility = 0
+-- Announcements are always first, the last pinned first, then the newest posts first
+ORDER BY post.announced_at DESC NULLS LAST, post.id DESC
 LIMIT $1
-OFFSET $2;
\ No newline at end of file
+OFFSET $2;
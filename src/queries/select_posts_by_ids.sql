@@ -0,0 +1,8 @@
+SELECT post.id, post.title, post.content, post.visibility, post.original_post_id, post.published, post.publish_at, post.pinned_at IS NOT NULL AS "pinned!", post.announced_at, post.created_at, post.updated_at, account.id AS author_id, account.username AS author_username, account.permission AS author_permission, account.avatar_url AS author_avatar_url, account.banner_url AS author_banner_url
+FROM post
+JOIN account ON post.author_id = account.id
+WHERE post.id = ANY($1)
+-- Drafts and scheduled posts are only visible by their author
+AND (post.published OR post.author_id = $2)
+-- Posts only visible by the followers of their author
+AND (post.visibility <> 2 OR post.author_id = $2 OR EXISTS (SELECT 1 FROM follow WHERE follower_id = $2 AND followed_id = post.author_id));
@@ -0,0 +1,7 @@
+SELECT post.id
+FROM post_tag
+JOIN post ON post_tag.post_id = post.id
+WHERE post_tag.tag = $1 AND post.published AND post.visibility = 0
+ORDER BY post.created_at DESC, post.id DESC
+LIMIT $2
+OFFSET $3;
@@ -0,0 +1,7 @@
+SELECT post.id
+FROM post
+-- Unlisted posts of the users followed are only reachable by their id
+WHERE post.published AND (post.author_id = $1 OR (post.visibility <> 1 AND post.author_id IN (SELECT followed_id FROM follow WHERE follower_id = $1)))
+ORDER BY post.created_at DESC, post.id DESC
+LIMIT $2
+OFFSET $3;
@@ -0,0 +1,7 @@
+SELECT post_tag.tag, COUNT(*) AS "posts_count!"
+FROM post_tag
+JOIN post ON post_tag.post_id = post.id
+WHERE post.published AND post.visibility = 0 AND post.created_at > NOW() - make_interval(hours => $1)
+GROUP BY post_tag.tag
+ORDER BY COUNT(*) DESC, post_tag.tag
+LIMIT $2;
@@ -0,0 +1,9 @@
+SELECT post.id
+FROM post
+WHERE post.author_id = $1 AND post.published
+-- Unlisted posts are only reachable by their id, except for their author
+AND (post.author_id = $2 OR post.visibility = 0 OR (post.visibility = 2 AND EXISTS (SELECT 1 FROM follow WHERE follower_id = $2 AND followed_id = post.author_id)))
+-- Pinned posts are first, the last pinned first
+ORDER BY post.pinned_at DESC NULLS LAST, post.created_at DESC, post.id DESC
+LIMIT $3
+OFFSET $4;
@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    extractors::auth_extractor::AuthUser,
+    utils::{
+        app_error::AppError,
+        pagination::{CursorPage, CursorPaginationParams},
+        post::{get_notification_post, get_public_posts},
+    },
+    AppState,
+};
+
+pub async fn add_bookmark_route(
+    AuthUser(auth_user): AuthUser,
+    Path(post_id): Path<i64>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<(), AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    if get_notification_post(&app_state.pool, post_id, Some(auth_user.id))
+        .await?
+        .is_none()
+    {
+        warn!("Cannot bookmark post {post_id} that doesn't exist");
+        return Err(AppError::forbidden_error(Some("Ce post n'existe pas.")));
+    }
+
+    sqlx::query!(
+        "INSERT INTO bookmark (account_id, post_id) VALUES ($1, $2) ON CONFLICT (account_id, post_id) DO NOTHING",
+        auth_user.id,
+        post_id
+    )
+    .execute(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error bookmarking post {post_id} for {} : {e}", auth_user.id);
+        AppError::internal_server_error()
+    })?;
+
+    Ok(())
+}
+
+pub async fn remove_bookmark_route(
+    AuthUser(auth_user): AuthUser,
+    Path(post_id): Path<i64>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<(), AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    let result = sqlx::query!(
+        "DELETE FROM bookmark WHERE account_id = $1 AND post_id = $2",
+        auth_user.id,
+        post_id
+    )
+    .execute(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!(
+            "Error removing bookmark of post {post_id} for {} : {e}",
+            auth_user.id
+        );
+        AppError::internal_server_error()
+    })?;
+
+    if result.rows_affected() == 0 {
+        warn!("Post {post_id} isn't in the bookmarks of {}", auth_user.id);
+        return Err(AppError::forbidden_error(Some(
+            "Ce post n'est pas dans tes favoris.",
+        )));
+    }
+
+    Ok(())
+}
+
+pub async fn get_bookmarks_route(
+    AuthUser(auth_user): AuthUser,
+    Query(pagination_params): Query<CursorPaginationParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<String, AppError> {
+    struct Bookmark {
+        id: i64,
+        post_id: i64,
+    }
+
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    let limit = pagination_params.limit();
+
+    let bookmarks = sqlx::query_as!(
+        Bookmark,
+        "SELECT id, post_id FROM bookmark WHERE account_id = $1 AND ($2::BIGINT IS NULL OR id < $2) ORDER BY id DESC LIMIT $3",
+        auth_user.id,
+        pagination_params.before,
+        limit
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting bookmarks of {} : {e}", auth_user.id);
+        AppError::internal_server_error()
+    })?;
+
+    let bookmarks = CursorPage::new(bookmarks, limit, |bookmark| bookmark.id);
+
+    let ids: Vec<i64> = bookmarks
+        .items
+        .iter()
+        .map(|bookmark| bookmark.post_id)
+        .collect();
+
+    let posts = get_public_posts(&app_state.pool, &ids, Some(auth_user.id)).await?;
+
+    Ok(json!(CursorPage {
+        items: posts,
+        next_cursor: bookmarks.next_cursor,
+    })
+    .to_string())
+}
@@ -0,0 +1,264 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    Extension, Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    extractors::auth_extractor::AuthUser,
+    models::comment::{CommentWithAuthor, PublicComment},
+    utils::{
+        app_error::AppError,
+        notification::send_notification,
+        pagination::{CursorPage, CursorPaginationParams},
+        post::{check_new_comment_data, get_notification_post},
+        real_time_event_management::{EventTracker, RealTimeEvent, WsEvent},
+    },
+    AppState,
+};
+
+#[derive(Deserialize)]
+pub struct CommentsParams {
+    /// Get the replies to this comment instead of the top level comments
+    pub parent_id: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct NewComment {
+    pub content: String,
+    pub parent_id: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct EditedComment {
+    pub content: String,
+}
+
+async fn get_comment(
+    app_state: &AppState,
+    comment_id: i64,
+) -> Result<Option<PublicComment>, AppError> {
+    let comment = sqlx::query_file_as!(
+        CommentWithAuthor,
+        "./src/queries/select_comment.sql",
+        comment_id
+    )
+    .fetch_optional(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting comment {comment_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    Ok(comment.map(PublicComment::from))
+}
+
+/// Get a comment of a post that has been written by the authenticated user
+async fn get_own_comment(
+    app_state: &AppState,
+    auth_user_id: i64,
+    post_id: i64,
+    comment_id: i64,
+) -> Result<PublicComment, AppError> {
+    let Some(comment) = get_comment(app_state, comment_id).await? else {
+        warn!("Comment {comment_id} doesn't exist");
+        return Err(AppError::forbidden_error(Some(
+            "Ce commentaire n'existe pas.",
+        )));
+    };
+
+    if comment.post_id != post_id {
+        warn!("Comment {comment_id} isn't a comment of post {post_id}");
+        return Err(AppError::forbidden_error(Some(
+            "Ce commentaire n'existe pas.",
+        )));
+    }
+
+    if comment.author.id != auth_user_id {
+        warn!("{auth_user_id} tried to modify comment {comment_id} of another user");
+        return Err(AppError::forbidden_error(Some(
+            "Tu ne peux modifier que tes propres commentaires.",
+        )));
+    }
+
+    Ok(comment)
+}
+
+pub async fn get_comments_route(
+    AuthUser(auth_user): AuthUser,
+    Path(post_id): Path<i64>,
+    Query(params): Query<CommentsParams>,
+    Query(pagination_params): Query<CursorPaginationParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<String, AppError> {
+    if get_notification_post(
+        &app_state.pool,
+        post_id,
+        auth_user.map(|auth_user| auth_user.id),
+    )
+    .await?
+    .is_none()
+    {
+        warn!("Cannot get comments of post {post_id} that doesn't exist");
+        return Err(AppError::forbidden_error(Some("Ce post n'existe pas.")));
+    }
+
+    let limit = pagination_params.limit();
+
+    let comments = sqlx::query_file_as!(
+        CommentWithAuthor,
+        "./src/queries/select_comments.sql",
+        post_id,
+        params.parent_id,
+        pagination_params.before,
+        limit
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting comments of post {post_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    let comments: Vec<PublicComment> = comments.into_iter().map(PublicComment::from).collect();
+
+    Ok(json!(CursorPage::new(comments, limit, |comment| comment.id)).to_string())
+}
+
+pub async fn publish_comment_route(
+    AuthUser(auth_user): AuthUser,
+    Extension(event_tracker): Extension<EventTracker>,
+    Path(post_id): Path<i64>,
+    State(app_state): State<Arc<AppState>>,
+    Json(comment): Json<NewComment>,
+) -> Result<String, AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("User not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    let content = comment.content.trim();
+
+    check_new_comment_data(auth_user.id, content)?;
+
+    let Some(post) = get_notification_post(&app_state.pool, post_id, Some(auth_user.id)).await?
+    else {
+        warn!("Cannot comment post {post_id} that doesn't exist");
+        return Err(AppError::forbidden_error(Some("Ce post n'existe pas.")));
+    };
+
+    let parent = if let Some(parent_id) = comment.parent_id {
+        match get_comment(&app_state, parent_id).await? {
+            Some(parent) if parent.post_id == post_id => Some(parent),
+            _ => {
+                warn!("Cannot reply to comment {parent_id} that isn't a comment of post {post_id}");
+                return Err(AppError::forbidden_error(Some(
+                    "Le commentaire auquel tu réponds n'existe pas.",
+                )));
+            }
+        }
+    } else {
+        None
+    };
+
+    let comment_id = sqlx::query_scalar!(
+        "INSERT INTO comment (post_id, author_id, parent_id, content) VALUES ($1, $2, $3, $4) RETURNING id",
+        post_id,
+        auth_user.id,
+        comment.parent_id,
+        content
+    )
+    .fetch_one(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error inserting comment of {} on post {post_id} : {e}", auth_user.id);
+        AppError::internal_server_error()
+    })?;
+
+    let Some(comment) = get_comment(&app_state, comment_id).await? else {
+        warn!("Comment {comment_id} deleted right after its creation");
+        return Err(AppError::internal_server_error());
+    };
+
+    event_tracker
+        .notify_post(
+            RealTimeEvent::NewComment { post_id },
+            post.visibility,
+            WsEvent::new_new_comment_event(&comment).to_string(),
+        )
+        .await;
+
+    let event = WsEvent::new_comment_event(&post, &comment);
+
+    if post.author.id != auth_user.id {
+        send_notification(&app_state.pool, &event_tracker, post.author.id, &event).await?;
+    }
+
+    if let Some(parent) = parent {
+        if parent.author.id != auth_user.id && parent.author.id != post.author.id {
+            send_notification(&app_state.pool, &event_tracker, parent.author.id, &event).await?;
+        }
+    }
+
+    Ok(comment_id.to_string())
+}
+
+pub async fn edit_comment_route(
+    AuthUser(auth_user): AuthUser,
+    Path((post_id, comment_id)): Path<(i64, i64)>,
+    State(app_state): State<Arc<AppState>>,
+    Json(comment): Json<EditedComment>,
+) -> Result<(), AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("User not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    let content = comment.content.trim();
+
+    check_new_comment_data(auth_user.id, content)?;
+
+    get_own_comment(&app_state, auth_user.id, post_id, comment_id).await?;
+
+    sqlx::query!(
+        "UPDATE comment SET content = $1, updated_at = NOW() WHERE id = $2",
+        content,
+        comment_id
+    )
+    .execute(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error editing comment {comment_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    Ok(())
+}
+
+/// Delete a comment of the authenticated user, its replies are kept as top-level comments
+pub async fn delete_comment_route(
+    AuthUser(auth_user): AuthUser,
+    Path((post_id, comment_id)): Path<(i64, i64)>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<(), AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("User not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    get_own_comment(&app_state, auth_user.id, post_id, comment_id).await?;
+
+    sqlx::query!("DELETE FROM comment WHERE id = $1", comment_id)
+        .execute(&app_state.pool)
+        .await
+        .map_err(|e| {
+            warn!("Error deleting comment {comment_id} : {e}");
+            AppError::internal_server_error()
+        })?;
+
+    Ok(())
+}
@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    Extension, Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tracing::warn;
+
+use crate::{
+    extractors::auth_extractor::AuthUser,
+    models::{
+        poll::NewPoll,
+        post::{PostPublication, PostVisibility},
+    },
+    utils::{
+        app_error::AppError,
+        pagination::PaginationParams,
+        poll::{check_new_poll, save_poll},
+        post::{
+            announce_new_post, attach_post_media, check_new_post_data, check_post_media,
+            get_notification_post, get_post_publication, get_public_posts,
+        },
+        real_time_event_management::{EventTracker, Users},
+    },
+    AppState,
+};
+
+/// The new version of a draft, the missing fields keep the visibility and the publication state of the draft
+#[derive(Deserialize)]
+pub struct EditedDraft {
+    pub title: String,
+    pub content: String,
+    #[serde(default)]
+    pub visibility: Option<PostVisibility>,
+    /// `false` publishes the draft, unless `publish_at` is set
+    #[serde(default)]
+    pub draft: Option<bool>,
+    /// Publish the post later, at this date
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub publish_at: Option<OffsetDateTime>,
+    /// The ids of the media uploaded with `/media`, in their order in the post
+    #[serde(default)]
+    pub media_ids: Vec<i64>,
+    #[serde(default)]
+    pub poll: Option<NewPoll>,
+}
+
+/// Get the drafts and scheduled posts of the authenticated user
+pub async fn get_drafts_route(
+    AuthUser(auth_user): AuthUser,
+    Query(pagination_params): Query<PaginationParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<String, AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    let ids = sqlx::query_scalar!(
+        "SELECT id FROM post WHERE author_id = $1 AND NOT published ORDER BY id DESC LIMIT $2 OFFSET $3",
+        auth_user.id,
+        pagination_params.limit(),
+        pagination_params.offset()
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting drafts of {} : {e}", auth_user.id);
+        AppError::internal_server_error()
+    })?;
+
+    let posts = get_public_posts(&app_state.pool, &ids, Some(auth_user.id)).await?;
+
+    Ok(json! {posts}.to_string())
+}
+
+/// Get the visibility and the publication state of a draft of the authenticated user after its edition
+async fn get_draft_publication(
+    pool: &PgPool,
+    auth_user_id: i64,
+    post_id: i64,
+    post: &EditedDraft,
+) -> Result<(PostVisibility, PostPublication), AppError> {
+    let draft = sqlx::query!(
+        "SELECT author_id, visibility, publish_at FROM post WHERE id = $1 AND NOT published",
+        post_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting author of draft {post_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    let Some(draft) = draft else {
+        warn!("Draft {post_id} doesn't exist");
+        return Err(AppError::forbidden_error(Some(
+            "Ce brouillon n'existe pas.",
+        )));
+    };
+
+    if draft.author_id != auth_user_id {
+        warn!("{auth_user_id} tried to edit draft {post_id} of another user");
+        return Err(AppError::forbidden_error(Some(
+            "Tu ne peux modifier que tes propres brouillons.",
+        )));
+    }
+
+    let visibility = post
+        .visibility
+        .unwrap_or_else(|| PostVisibility::from(draft.visibility));
+
+    //Only publish the draft when the client asks for it
+    let publication = match (post.draft, post.publish_at) {
+        (None, None) => match draft.publish_at {
+            Some(publish_at) => PostPublication::Scheduled(publish_at),
+            None => PostPublication::Draft,
+        },
+        (draft, publish_at) => {
+            get_post_publication(auth_user_id, draft.unwrap_or(false), publish_at)?
+        }
+    };
+
+    Ok((visibility, publication))
+}
+
+/// Edit a draft or a scheduled post, and publish it if it is neither anymore
+pub async fn edit_draft_route(
+    AuthUser(auth_user): AuthUser,
+    Extension(users): Extension<Users>,
+    Extension(event_tracker): Extension<EventTracker>,
+    Path(post_id): Path<i64>,
+    State(app_state): State<Arc<AppState>>,
+    Json(post): Json<EditedDraft>,
+) -> Result<(), AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("User not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    let title = post.title.trim();
+    let content = post.content.trim();
+
+    check_new_post_data(auth_user.id, title, content)?;
+
+    let (visibility, publication) =
+        get_draft_publication(&app_state.pool, auth_user.id, post_id, &post).await?;
+
+    check_post_media(
+        &app_state.pool,
+        auth_user.id,
+        Some(post_id),
+        &post.media_ids,
+    )
+    .await?;
+
+    if let Some(poll) = &post.poll {
+        check_new_poll(auth_user.id, poll, publication)?;
+    }
+
+    let internal_error = |e: sqlx::Error| {
+        warn!("Error editing draft {post_id} : {e}");
+        AppError::internal_server_error()
+    };
+
+    //The draft is only published with all its media and its poll
+    let mut transaction = app_state.pool.begin().await.map_err(internal_error)?;
+
+    //The draft may have been published by the scheduler in the meantime
+    let result = sqlx::query!(
+        "UPDATE post SET title = $1, content = $2, visibility = $3, published = $4, publish_at = $5, created_at = CASE WHEN $4 THEN NOW() ELSE created_at END, updated_at = NOW() WHERE id = $6 AND NOT published",
+        title,
+        content,
+        visibility as i32,
+        publication.is_published(),
+        publication.publish_at(),
+        post_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(internal_error)?;
+
+    if result.rows_affected() == 0 {
+        warn!("Draft {post_id} has already been published");
+        return Err(AppError::forbidden_error(Some(
+            "Ce brouillon a déjà été publié.",
+        )));
+    }
+
+    attach_post_media(&mut transaction, post_id, &post.media_ids).await?;
+    save_poll(&mut transaction, post_id, post.poll.as_ref()).await?;
+    transaction.commit().await.map_err(internal_error)?;
+
+    //The draft is saved, an error now would make the client edit it again
+    if publication.is_published() {
+        let announced =
+            match get_notification_post(&app_state.pool, post_id, Some(auth_user.id)).await {
+                Ok(Some(new_post)) => announce_new_post(
+                    &app_state.pool,
+                    &users,
+                    &event_tracker,
+                    &new_post,
+                    title,
+                    content,
+                )
+                .await
+                .is_ok(),
+                _ => false,
+            };
+
+        if !announced {
+            warn!("Draft {post_id} was published without being announced");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use std::{
+    convert::Infallible,
+    sync::{atomic::Ordering, Arc},
+};
+
+use axum::{
+    extract::{ws::Message, Query, State},
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+    Extension,
+};
+use axum_extra::extract::CookieJar;
+use futures_util::{stream, Stream, StreamExt};
+use serde::Deserialize;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::{
+    extractors::auth_extractor::{AuthUser, InnerAuthUser},
+    utils::{
+        app_error::AppError,
+        authentification::authentificate,
+        notification::get_missed_events,
+        real_time_event_management::{EventTracker, RealTimeEvent, UserConnection, Users, WsEvent},
+        ws_protocol::parse_query_subscription,
+    },
+    AppState, NEXT_NOT_CONNECTED_USER_ID,
+};
+
+#[derive(Deserialize)]
+pub struct EventsParams {
+    /// The events to subscribe to, separated by commas, like `new_comment:42,connected_users_count_update`
+    #[serde(default)]
+    pub subscriptions: String,
+}
+
+/// Remove a SSE connection from the connections when its stream is dropped
+struct SseConnection {
+    id: i64,
+    user: Arc<RwLock<UserConnection>>,
+    users: Users,
+    event_tracker: EventTracker,
+}
+
+impl Drop for SseConnection {
+    fn drop(&mut self) {
+        let id = self.id;
+        let user = self.user.clone();
+        let users = self.users.clone();
+        let event_tracker = self.event_tracker.clone();
+        tokio::spawn(async move {
+            event_tracker.disconnect(id, user, users).await;
+            info!("SSE connection {id} closed");
+        });
+    }
+}
+
+/// The user authenticated with the Bearer token, or else with the `session` cookie
+async fn with_session_cookie(
+    auth_user: Option<InnerAuthUser>,
+    cookies: &CookieJar,
+    app_state: &Arc<AppState>,
+) -> Result<Option<InnerAuthUser>, AppError> {
+    let (None, Some(cookie)) = (&auth_user, cookies.get("session")) else {
+        return Ok(auth_user);
+    };
+
+    let AuthUser(auth_user) = authentificate(app_state.clone(), cookie.value()).await?;
+    if auth_user.is_none() {
+        info!("SSE client sent an invalid session cookie");
+    }
+
+    Ok(auth_user)
+}
+
+/// Stream the same events as `/ws` with SSE, for the clients that can't use `/ws`
+/// The events stored as notifications have their id as SSE id, to resume with the `Last-Event-ID` header
+/// The client authenticates with a Bearer token, or with the `session` cookie like `/ws`
+pub async fn events_route(
+    AuthUser(auth_user): AuthUser,
+    Query(params): Query<EventsParams>,
+    headers: HeaderMap,
+    cookies: CookieJar,
+    Extension(users): Extension<Users>,
+    Extension(event_tracker): Extension<EventTracker>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let auth_user = with_session_cookie(auth_user, &cookies, &app_state).await?;
+    let user_id = auth_user.as_ref().map(|auth_user| auth_user.id);
+
+    let mut subscriptions = Vec::new();
+    for subscription in params.subscriptions.split(',').filter(|s| !s.is_empty()) {
+        let Some(event) = parse_query_subscription(subscription) else {
+            warn!("Invalid SSE subscription `{subscription}`");
+            return Err(AppError::forbidden_error(Some(format!(
+                "L'abonnement `{subscription}` est invalide."
+            ))));
+        };
+        if !event_tracker.can_subscribe(user_id, &event).await {
+            warn!("{user_id:?} isn't allowed to subscribe to {event:?}");
+            return Err(AppError::forbidden_error(Some(format!(
+                "Tu ne peux pas t'abonner à `{subscription}`."
+            ))));
+        }
+        subscriptions.push(event);
+    }
+
+    let (connection, queue) = UserConnection::new_queued(user_id);
+    let closed = connection.closed();
+    let user = Arc::new(RwLock::new(connection));
+
+    let id = match &auth_user {
+        Some(auth_user) => {
+            event_tracker
+                .subscribe(
+                    RealTimeEvent::UserNotification {
+                        user_id: auth_user.id,
+                    },
+                    user.clone(),
+                )
+                .await;
+            auth_user.id
+        }
+        None => NEXT_NOT_CONNECTED_USER_ID.fetch_sub(1, Ordering::Relaxed),
+    };
+
+    for event in subscriptions {
+        event_tracker.subscribe(event, user.clone()).await;
+    }
+
+    event_tracker
+        .add_to_users(id, users.clone(), user.clone())
+        .await;
+
+    info!("SSE connection {id} opened");
+
+    let last_event_id = headers
+        .get("Last-Event-ID")
+        .and_then(|last_event_id| last_event_id.to_str().ok()?.parse::<i64>().ok());
+
+    //The connection already receives the new events, so none is lost between the replay and the live events
+    let mut missed_events = Vec::new();
+    if let (Some(auth_user), Some(last_event_id)) = (&auth_user, last_event_id) {
+        if let Ok((events, truncated)) =
+            get_missed_events(&app_state.pool, auth_user.id, last_event_id).await
+        {
+            let replayed_event = WsEvent::new_replayed_event(events.len(), truncated);
+            missed_events = events;
+            missed_events.push(replayed_event);
+        }
+    }
+
+    let connection = SseConnection {
+        id,
+        user,
+        users,
+        event_tracker,
+    };
+
+    let live_events = stream::unfold(
+        (connection, queue, closed),
+        |(connection, mut queue, mut closed)| async move {
+            let message = tokio::select! {
+                biased;
+                //The server closed the connection, because it was too slow
+                Ok(()) = closed.changed() => None,
+                message = queue.recv() => message,
+            }?;
+            Some((message, (connection, queue, closed)))
+        },
+    );
+
+    let events = stream::iter(
+        missed_events
+            .into_iter()
+            .map(|event| Message::Text(event.to_string())),
+    )
+    .chain(live_events)
+    .filter_map(|message| async move {
+        let Message::Text(text) = message else {
+            return None;
+        };
+        let event_id = serde_json::from_str::<serde_json::Value>(&text)
+            .ok()
+            .and_then(|event| event["id"].as_i64());
+        let event = Event::default().data(text);
+        Some(Ok(match event_id {
+            Some(event_id) => event.id(event_id.to_string()),
+            None => event,
+        }))
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
@@ -11,7 +11,9 @@ use crate::{
     extractors::auth_extractor::AuthUser,
     utils::{
         app_error::AppError,
-        real_time_event_management::{EventTracker, RealTimeEvent, Users},
+        notification::send_notification,
+        post::get_public_post_author,
+        real_time_event_management::{EventTracker, WsEvent},
         register::Record,
     },
     AppState,
@@ -23,7 +25,6 @@ pub struct Count {
 
 pub async fn follow_user_route(
     AuthUser(auth_user): AuthUser,
-    Extension(users): Extension<Users>,
     Extension(event_tracker): Extension<EventTracker>,
     Path(user_username): Path<String>,
     State(app_state): State<Arc<AppState>>,
@@ -105,23 +106,9 @@ pub async fn follow_user_route(
         AppError::internal_server_error()
     })?;
 
-    let mut writer = users.write().await;
-
-    let Some(user) = writer.get_mut(&auth_user.id) else {
-        return Ok(());
-    };
-
-    //TODO Not sure if that's a good idea...
-    for connection in user {
-        event_tracker
-            .subscribe(
-                RealTimeEvent::NewPostNotification {
-                    followed_user_id: user_id,
-                },
-                connection.clone(),
-            )
-            .await;
-    }
+    let follower = get_public_post_author(&app_state.pool, auth_user.id).await?;
+    let event = WsEvent::new_follow_event(&follower);
+    send_notification(&app_state.pool, &event_tracker, user_id, &event).await?;
 
     Ok(())
 }
@@ -0,0 +1,31 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    extractors::auth_extractor::AuthUser,
+    utils::{app_error::AppError, post::get_public_posts},
+    AppState,
+};
+
+pub async fn get_post_route(
+    AuthUser(auth_user): AuthUser,
+    Path(post_id): Path<i64>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<String, AppError> {
+    let posts = get_public_posts(
+        &app_state.pool,
+        &[post_id],
+        auth_user.map(|auth_user| auth_user.id),
+    )
+    .await?;
+
+    let Some(post) = posts.into_iter().next() else {
+        warn!("Post {post_id} doesn't exist");
+        return Err(AppError::forbidden_error(Some("Ce post n'existe pas.")));
+    };
+
+    Ok(json! {post}.to_string())
+}
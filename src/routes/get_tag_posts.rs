@@ -0,0 +1,50 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    extractors::auth_extractor::AuthUser,
+    utils::{
+        app_error::AppError, pagination::PaginationParams, post::get_public_posts,
+        tag::normalize_tag,
+    },
+    AppState,
+};
+
+pub async fn get_tag_posts_route(
+    AuthUser(auth_user): AuthUser,
+    Path(tag): Path<String>,
+    Query(pagination_params): Query<PaginationParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<String, AppError> {
+    let Some(tag) = normalize_tag(&tag) else {
+        warn!("Invalid tag `{tag}`");
+        return Err(AppError::forbidden_error(Some(format!(
+            "Le tag `{tag}` est invalide."
+        ))));
+    };
+
+    let ids = sqlx::query_file_scalar!(
+        "./src/queries/select_posts_with_tag.sql",
+        tag,
+        pagination_params.limit(),
+        pagination_params.offset()
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting posts with tag `{tag}` : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    let posts = get_public_posts(
+        &app_state.pool,
+        &ids,
+        auth_user.map(|auth_user| auth_user.id),
+    )
+    .await?;
+
+    Ok(json! {posts}.to_string())
+}
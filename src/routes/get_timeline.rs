@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    extractors::auth_extractor::AuthUser,
+    utils::{app_error::AppError, pagination::PaginationParams, post::get_public_posts},
+    AppState,
+};
+
+/// Get the posts and reposts of the authenticated user and of the users it follows
+pub async fn get_timeline_route(
+    AuthUser(auth_user): AuthUser,
+    Query(pagination_params): Query<PaginationParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<String, AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    let ids = sqlx::query_file_scalar!(
+        "./src/queries/select_timeline_posts.sql",
+        auth_user.id,
+        pagination_params.limit(),
+        pagination_params.offset()
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting timeline of {} : {e}", auth_user.id);
+        AppError::internal_server_error()
+    })?;
+
+    let posts = get_public_posts(&app_state.pool, &ids, Some(auth_user.id)).await?;
+
+    Ok(json! {posts}.to_string())
+}
@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    utils::{app_error::AppError, pagination::clamp_limit},
+    AppState,
+};
+
+/// Size of the sliding window used to compute the trending tags
+const TRENDING_TAGS_WINDOW_HOURS: i32 = 24;
+
+#[derive(Deserialize)]
+pub struct TrendingTagsParams {
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct TrendingTag {
+    pub tag: String,
+    pub posts_count: i64,
+}
+
+pub async fn get_trending_tags_route(
+    Query(params): Query<TrendingTagsParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<String, AppError> {
+    let limit = clamp_limit(params.limit);
+
+    let tags = sqlx::query_file_as!(
+        TrendingTag,
+        "./src/queries/select_trending_tags.sql",
+        TRENDING_TAGS_WINDOW_HOURS,
+        limit
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting trending tags : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    Ok(json! {tags}.to_string())
+}
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    extractors::auth_extractor::AuthUser,
+    utils::{app_error::AppError, pagination::PaginationParams, post::get_public_posts},
+    AppState,
+};
+
+pub async fn get_user_posts_route(
+    AuthUser(auth_user): AuthUser,
+    Path(username): Path<String>,
+    Query(pagination_params): Query<PaginationParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<String, AppError> {
+    let user_id = sqlx::query_scalar!("SELECT id FROM account WHERE username = $1", username)
+        .fetch_optional(&app_state.pool)
+        .await
+        .map_err(|e| {
+            warn!("Error getting id of user {username} : {e}");
+            AppError::internal_server_error()
+        })?;
+
+    let Some(user_id) = user_id else {
+        warn!("Cannot get posts of user `{username}` that doesn't exist");
+        return Err(AppError::forbidden_error(Some(format!(
+            "L'utilisateur {username} n'existe pas."
+        ))));
+    };
+
+    let viewer_id = auth_user.map(|auth_user| auth_user.id);
+
+    let ids = sqlx::query_file_scalar!(
+        "./src/queries/select_user_posts.sql",
+        user_id,
+        viewer_id,
+        pagination_params.limit(),
+        pagination_params.offset()
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting posts of {user_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    let posts = get_public_posts(&app_state.pool, &ids, viewer_id).await?;
+
+    Ok(json! {posts}.to_string())
+}
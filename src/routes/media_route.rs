@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Multipart, Path, State},
+    response::IntoResponse,
+};
+use hyper::header;
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    extractors::auth_extractor::AuthUser,
+    models::media::PublicMedia,
+    utils::{
+        app_error::AppError,
+        avatar::{generate_identicon, parse_default_avatar_key},
+        media::{get_content_type, process_image, read_uploaded_file},
+        media_storage::is_valid_storage_key,
+    },
+    AppState,
+};
+
+/// Upload an image, it can then be attached to a post with its id
+pub async fn upload_media_route(
+    AuthUser(auth_user): AuthUser,
+    State(app_state): State<Arc<AppState>>,
+    multipart: Multipart,
+) -> Result<String, AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    let data = read_uploaded_file(multipart, auth_user.id).await?;
+
+    //Decoding and encoding images is CPU intensive
+    let processed_image = tokio::task::spawn_blocking(move || process_image(&data))
+        .await
+        .map_err(|e| {
+            warn!("Error processing image of {} : {e}", auth_user.id);
+            AppError::internal_server_error()
+        })??;
+    let image = processed_image.image;
+
+    let name = format!("{:032x}", rand::random::<u128>());
+    let storage_key = format!("{name}.{}", image.extension);
+    let thumbnail_storage_key = format!("{name}-thumbnail.{}", image.extension);
+
+    let storage = &app_state.media_storage;
+    let url = storage.save(&storage_key, image.data).await?;
+    let thumbnail_url = match storage
+        .save(&thumbnail_storage_key, processed_image.thumbnail)
+        .await
+    {
+        Ok(thumbnail_url) => thumbnail_url,
+        Err(e) => {
+            storage.delete(&storage_key).await.ok();
+            return Err(e);
+        }
+    };
+
+    let media = sqlx::query_as!(
+        PublicMedia,
+        "INSERT INTO media (owner_id, content_type, width, height, storage_key, url, thumbnail_storage_key, thumbnail_url) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id, content_type, width, height, url, thumbnail_url",
+        auth_user.id,
+        image.content_type,
+        i32::try_from(image.width).unwrap_or(i32::MAX),
+        i32::try_from(image.height).unwrap_or(i32::MAX),
+        storage_key,
+        url,
+        thumbnail_storage_key,
+        thumbnail_url
+    )
+    .fetch_one(&app_state.pool)
+    .await;
+
+    let media = match media {
+        Ok(media) => media,
+        Err(e) => {
+            warn!("Error inserting media of {} : {e}", auth_user.id);
+            storage.delete(&storage_key).await.ok();
+            storage.delete(&thumbnail_storage_key).await.ok();
+            return Err(AppError::internal_server_error());
+        }
+    };
+
+    Ok(json! {media}.to_string())
+}
+
+/// Serve a media file stored by the server
+pub async fn get_media_file_route(
+    Path(key): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let content_type = get_content_type(&key).filter(|_| is_valid_storage_key(&key));
+
+    let Some(content_type) = content_type else {
+        warn!("Invalid media key `{key}`");
+        return Err(AppError::forbidden_error(Some("Ce fichier n'existe pas.")));
+    };
+
+    if let Some(user_id) = parse_default_avatar_key(&key) {
+        let identicon = tokio::task::spawn_blocking(move || generate_identicon(user_id))
+            .await
+            .map_err(|e| {
+                warn!("Error generating default avatar of {user_id} : {e}");
+                AppError::internal_server_error()
+            })??;
+
+        return Ok((
+            [
+                (header::CONTENT_TYPE, content_type),
+                (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+            ],
+            identicon,
+        ));
+    }
+
+    let Some(data) = app_state.media_storage.load(&key).await? else {
+        warn!("Media file {key} doesn't exist");
+        return Err(AppError::forbidden_error(Some("Ce fichier n'existe pas.")));
+    };
+
+    //The content of a key never changes
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+        ],
+        data,
+    ))
+}
@@ -1,9 +1,27 @@
 pub mod a2f_login_route;
+pub mod bookmark_route;
+pub mod comment_route;
+pub mod draft_route;
 pub mod email_confirm_route;
+pub mod events_route;
 pub mod follow_user_route;
+pub mod get_post;
 pub mod get_posts;
+pub mod get_tag_posts;
+pub mod get_timeline;
+pub mod get_trending_tags;
+pub mod get_user_posts;
 pub mod login_route;
+pub mod media_route;
+pub mod notification_route;
 pub mod ok_route;
+pub mod pin_route;
+pub mod poll_route;
+pub mod post_reaction_route;
+pub mod presence_route;
+pub mod profile_image_route;
 pub mod publish_post;
 pub mod register_route;
+pub mod repost_route;
+pub mod search_route;
 pub mod ws_route;
@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    extractors::auth_extractor::AuthUser,
+    models::notification::{PublicNotification, ReadNotifications},
+    utils::{
+        app_error::AppError,
+        notification::get_unread_notifications_count,
+        pagination::{CursorPage, CursorPaginationParams},
+    },
+    AppState,
+};
+
+pub async fn get_notifications_route(
+    AuthUser(auth_user): AuthUser,
+    Query(pagination_params): Query<CursorPaginationParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<String, AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    let limit = pagination_params.limit();
+
+    let notifications = sqlx::query_as!(
+        PublicNotification,
+        "SELECT event_seq AS id, kind, content, read, created_at FROM notification WHERE recipient_id = $1 AND ($2::BIGINT IS NULL OR event_seq < $2) ORDER BY event_seq DESC LIMIT $3",
+        auth_user.id,
+        pagination_params.before,
+        limit
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting notifications of {} : {e}", auth_user.id);
+        AppError::internal_server_error()
+    })?;
+
+    let notifications = CursorPage::new(notifications, limit, |notification| notification.id);
+
+    Ok(json!(notifications).to_string())
+}
+
+pub async fn get_unread_notifications_count_route(
+    AuthUser(auth_user): AuthUser,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<String, AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    let count = get_unread_notifications_count(&app_state.pool, auth_user.id).await?;
+
+    Ok(count.to_string())
+}
+
+/// Mark notifications as read, by their ids and/or all of them up to a notification
+/// Returns the number of unread notifications left
+pub async fn read_notifications_route(
+    AuthUser(auth_user): AuthUser,
+    State(app_state): State<Arc<AppState>>,
+    Json(read_notifications): Json<ReadNotifications>,
+) -> Result<String, AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    if read_notifications.ids.is_none() && read_notifications.up_to.is_none() {
+        warn!(
+            "{} tried to read notifications without ids nor cursor",
+            auth_user.id
+        );
+        return Err(AppError::forbidden_error(Some(
+            "Tu dois préciser les notifications à marquer comme lues.",
+        )));
+    }
+
+    sqlx::query!(
+        "UPDATE notification SET read = TRUE WHERE recipient_id = $1 AND NOT read AND (event_seq = ANY($2) OR event_seq <= $3)",
+        auth_user.id,
+        read_notifications.ids.as_deref(),
+        read_notifications.up_to
+    )
+    .execute(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error reading notifications of {} : {e}", auth_user.id);
+        AppError::internal_server_error()
+    })?;
+
+    let count = get_unread_notifications_count(&app_state.pool, auth_user.id).await?;
+
+    Ok(count.to_string())
+}
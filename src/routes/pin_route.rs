@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use sqlx::PgPool;
+use tracing::warn;
+
+use crate::{
+    extractors::auth_extractor::AuthUser,
+    models::{account::AccountPermission, post::PostVisibility},
+    utils::app_error::AppError,
+    AppState,
+};
+
+const MAX_PINNED_POSTS: i64 = 3;
+
+struct PinnablePost {
+    author_id: i64,
+    published: bool,
+    visibility: PostVisibility,
+}
+
+async fn get_pinnable_post(pool: &PgPool, post_id: i64) -> Result<PinnablePost, AppError> {
+    let post = sqlx::query_as!(
+        PinnablePost,
+        "SELECT author_id, published, visibility FROM post WHERE id = $1",
+        post_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting post {post_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    let Some(post) = post else {
+        warn!("Cannot pin post {post_id} that doesn't exist");
+        return Err(AppError::forbidden_error(Some("Ce post n'existe pas.")));
+    };
+
+    //Drafts and scheduled posts can't be pinned before being published
+    if !post.published {
+        warn!("Cannot pin post {post_id} that isn't published");
+        return Err(AppError::forbidden_error(Some(
+            "Ce post n'est pas encore publié.",
+        )));
+    }
+
+    Ok(post)
+}
+
+/// Pin a post on the profile of its author
+pub async fn pin_post_route(
+    AuthUser(auth_user): AuthUser,
+    Path(post_id): Path<i64>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<(), AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    let post = get_pinnable_post(&app_state.pool, post_id).await?;
+
+    if post.author_id != auth_user.id {
+        warn!(
+            "{} tried to pin post {post_id} of {}",
+            auth_user.id, post.author_id
+        );
+        return Err(AppError::forbidden_error(Some(
+            "Tu ne peux épingler que tes propres posts.",
+        )));
+    }
+
+    let internal_error = |e: sqlx::Error| {
+        warn!("Error pinning post {post_id} : {e}");
+        AppError::internal_server_error()
+    };
+
+    let mut transaction = app_state.pool.begin().await.map_err(internal_error)?;
+
+    //Locking the account makes the concurrent pins of its posts wait, so they can't exceed the limit
+    sqlx::query!(
+        "SELECT id FROM account WHERE id = $1 FOR UPDATE",
+        auth_user.id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(internal_error)?;
+
+    let pinned_count = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM post WHERE author_id = $1 AND pinned_at IS NOT NULL AND id <> $2"#,
+        auth_user.id,
+        post_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(internal_error)?;
+
+    if pinned_count >= MAX_PINNED_POSTS {
+        warn!("{} already pinned {pinned_count} posts", auth_user.id);
+        return Err(AppError::forbidden_error(Some(
+            "Tu ne peux pas épingler plus de 3 posts.",
+        )));
+    }
+
+    sqlx::query!(
+        "UPDATE post SET pinned_at = NOW() WHERE id = $1 AND pinned_at IS NULL",
+        post_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(internal_error)?;
+
+    transaction.commit().await.map_err(internal_error)
+}
+
+/// Unpin a post from the profile of its author
+pub async fn unpin_post_route(
+    AuthUser(auth_user): AuthUser,
+    Path(post_id): Path<i64>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<(), AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    let result = sqlx::query!(
+        "UPDATE post SET pinned_at = NULL WHERE id = $1 AND author_id = $2 AND pinned_at IS NOT NULL",
+        post_id,
+        auth_user.id
+    )
+    .execute(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error unpinning post {post_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    if result.rows_affected() == 0 {
+        warn!("Post {post_id} isn't pinned by {}", auth_user.id);
+        return Err(AppError::forbidden_error(Some(
+            "Ce post n'est pas épinglé sur ton profil.",
+        )));
+    }
+
+    Ok(())
+}
+
+async fn check_is_moderator(pool: &PgPool, user_id: i64) -> Result<(), AppError> {
+    let permission = sqlx::query_scalar!("SELECT permission FROM account WHERE id = $1", user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| {
+            warn!("Error getting permission of {user_id} : {e}");
+            AppError::internal_server_error()
+        })?;
+
+    if !AccountPermission::from(permission).is_moderator() {
+        warn!("{user_id} tried to manage announcements without being a moderator");
+        return Err(AppError::forbidden_error(Some(
+            "Seuls les modérateurs peuvent gérer les annonces.",
+        )));
+    }
+
+    Ok(())
+}
+
+/// Pin a post as a site-wide announcement, at the top of the posts
+pub async fn pin_announcement_route(
+    AuthUser(auth_user): AuthUser,
+    Path(post_id): Path<i64>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<(), AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    check_is_moderator(&app_state.pool, auth_user.id).await?;
+
+    let post = get_pinnable_post(&app_state.pool, post_id).await?;
+
+    if post.visibility != PostVisibility::Public {
+        warn!("Cannot make an announcement of post {post_id} that isn't public");
+        return Err(AppError::forbidden_error(Some(
+            "Seul un post public peut être une annonce.",
+        )));
+    }
+
+    sqlx::query!(
+        "UPDATE post SET announced_at = NOW() WHERE id = $1 AND announced_at IS NULL",
+        post_id
+    )
+    .execute(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error making an announcement of post {post_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    Ok(())
+}
+
+/// Remove a post from the site-wide announcements
+pub async fn unpin_announcement_route(
+    AuthUser(auth_user): AuthUser,
+    Path(post_id): Path<i64>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<(), AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    check_is_moderator(&app_state.pool, auth_user.id).await?;
+
+    let result = sqlx::query!(
+        "UPDATE post SET announced_at = NULL WHERE id = $1 AND announced_at IS NOT NULL",
+        post_id
+    )
+    .execute(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error removing the announcement of post {post_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    if result.rows_affected() == 0 {
+        warn!("Post {post_id} isn't an announcement");
+        return Err(AppError::forbidden_error(Some(
+            "Ce post n'est pas une annonce.",
+        )));
+    }
+
+    Ok(())
+}
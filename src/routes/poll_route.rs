@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use time::OffsetDateTime;
+use tracing::warn;
+
+use crate::{
+    extractors::auth_extractor::AuthUser,
+    utils::{
+        app_error::AppError,
+        poll::{get_poll_results, get_public_polls, save_vote},
+        post::get_notification_post,
+        real_time_event_management::{EventTracker, RealTimeEvent, WsEvent},
+    },
+    AppState,
+};
+
+#[derive(Deserialize)]
+pub struct PollVote {
+    pub option_ids: Vec<i64>,
+}
+
+/// Vote to the poll of a post, the votes can't be changed
+/// Returns the poll with its results
+pub async fn vote_route(
+    AuthUser(auth_user): AuthUser,
+    Extension(event_tracker): Extension<EventTracker>,
+    Path(post_id): Path<i64>,
+    State(app_state): State<Arc<AppState>>,
+    Json(vote): Json<PollVote>,
+) -> Result<String, AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    let Some(post) = get_notification_post(&app_state.pool, post_id, Some(auth_user.id)).await?
+    else {
+        warn!("Cannot vote to the poll of post {post_id} that doesn't exist");
+        return Err(AppError::forbidden_error(Some("Ce post n'existe pas.")));
+    };
+
+    let poll = sqlx::query!(
+        "SELECT multiple_choice, expires_at FROM poll WHERE post_id = $1",
+        post_id
+    )
+    .fetch_optional(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting poll of post {post_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    let Some(poll) = poll else {
+        warn!("Post {post_id} has no poll");
+        return Err(AppError::forbidden_error(Some(
+            "Ce post n'a pas de sondage.",
+        )));
+    };
+
+    if poll.expires_at <= OffsetDateTime::now_utc() {
+        warn!(
+            "{} tried to vote to the closed poll of post {post_id}",
+            auth_user.id
+        );
+        return Err(AppError::forbidden_error(Some("Ce sondage est terminé.")));
+    }
+
+    let mut option_ids = vote.option_ids;
+    option_ids.sort_unstable();
+    option_ids.dedup();
+
+    if option_ids.is_empty() || (!poll.multiple_choice && option_ids.len() > 1) {
+        warn!(
+            "{} tried to vote for {} options to the poll of post {post_id}",
+            auth_user.id,
+            option_ids.len()
+        );
+        return Err(AppError::forbidden_error(Some(if poll.multiple_choice {
+            "Tu dois choisir au moins une option."
+        } else {
+            "Tu dois choisir une seule option."
+        })));
+    }
+
+    let options_count = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM poll_option WHERE post_id = $1 AND id = ANY($2)"#,
+        post_id,
+        &option_ids
+    )
+    .fetch_one(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error checking options {option_ids:?} of poll of post {post_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    if usize::try_from(options_count).ok() != Some(option_ids.len()) {
+        warn!("Options {option_ids:?} aren't all options of the poll of post {post_id}");
+        return Err(AppError::forbidden_error(Some(
+            "Une des options n'existe pas.",
+        )));
+    }
+
+    save_vote(&app_state.pool, post_id, auth_user.id, &option_ids).await?;
+
+    let results = get_poll_results(&app_state.pool, post_id).await?;
+
+    event_tracker
+        .notify_post(
+            RealTimeEvent::PollUpdated { post_id },
+            post.visibility,
+            WsEvent::new_poll_updated_event(&results).to_string(),
+        )
+        .await;
+
+    let poll = get_public_polls(&app_state.pool, &[post_id], Some(auth_user.id))
+        .await?
+        .remove(&post_id);
+
+    Ok(json! {poll}.to_string())
+}
@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    Extension,
+};
+use tracing::warn;
+
+use crate::{
+    extractors::auth_extractor::AuthUser,
+    models::{post::PostVisibility, reaction::ReactionKind},
+    utils::{
+        app_error::AppError,
+        notification::send_notification,
+        post::{get_notification_post, get_public_post_author, get_reactions_counts},
+        real_time_event_management::{EventTracker, RealTimeEvent, WsEvent},
+    },
+    AppState,
+};
+
+fn parse_reaction_kind(kind: &str) -> Result<ReactionKind, AppError> {
+    ReactionKind::from_name(kind).ok_or_else(|| {
+        warn!("Unknown reaction `{kind}`");
+        AppError::forbidden_error(Some(format!("La réaction `{kind}` n'existe pas.")))
+    })
+}
+
+pub async fn add_reaction_route(
+    AuthUser(auth_user): AuthUser,
+    Extension(event_tracker): Extension<EventTracker>,
+    Path((post_id, kind)): Path<(i64, String)>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<(), AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    let kind = parse_reaction_kind(&kind)?;
+
+    let Some(post) = get_notification_post(&app_state.pool, post_id, Some(auth_user.id)).await?
+    else {
+        warn!("Cannot react to post {post_id} that doesn't exist");
+        return Err(AppError::forbidden_error(Some("Ce post n'existe pas.")));
+    };
+
+    //`xmax` is 0 for a new row, and set when an existing reaction is updated
+    let inserted = sqlx::query_scalar!(
+        r#"INSERT INTO reaction (post_id, account_id, kind) VALUES ($1, $2, $3) ON CONFLICT (post_id, account_id) DO UPDATE SET kind = EXCLUDED.kind, created_at = NOW() RETURNING (xmax = 0) AS "inserted!""#,
+        post_id,
+        auth_user.id,
+        kind.name()
+    )
+    .fetch_one(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!(
+            "Error adding reaction `{}` from {} to post {post_id} : {e}",
+            kind.name(),
+            auth_user.id
+        );
+        AppError::internal_server_error()
+    })?;
+
+    notify_reactions_update(&app_state, &event_tracker, post_id, post.visibility).await?;
+
+    //Changing the kind of a reaction doesn't notify the author again
+    if inserted && post.author.id != auth_user.id {
+        let user = get_public_post_author(&app_state.pool, auth_user.id).await?;
+        let event = WsEvent::new_reaction_event(&post, kind, &user);
+        send_notification(&app_state.pool, &event_tracker, post.author.id, &event).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn remove_reaction_route(
+    AuthUser(auth_user): AuthUser,
+    Extension(event_tracker): Extension<EventTracker>,
+    Path((post_id, kind)): Path<(i64, String)>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<(), AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    let kind = parse_reaction_kind(&kind)?;
+
+    let Some(post) = get_notification_post(&app_state.pool, post_id, Some(auth_user.id)).await?
+    else {
+        warn!("Cannot remove reaction from post {post_id} that doesn't exist");
+        return Err(AppError::forbidden_error(Some("Ce post n'existe pas.")));
+    };
+
+    let result = sqlx::query!(
+        "DELETE FROM reaction WHERE post_id = $1 AND account_id = $2 AND kind = $3",
+        post_id,
+        auth_user.id,
+        kind.name()
+    )
+    .execute(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!(
+            "Error removing reaction `{}` from {} to post {post_id} : {e}",
+            kind.name(),
+            auth_user.id
+        );
+        AppError::internal_server_error()
+    })?;
+
+    if result.rows_affected() == 0 {
+        warn!(
+            "{} has no reaction `{}` to post {post_id}",
+            auth_user.id,
+            kind.name()
+        );
+        return Err(AppError::forbidden_error(Some(
+            "Tu n'as pas ajouté cette réaction à ce post.",
+        )));
+    }
+
+    notify_reactions_update(&app_state, &event_tracker, post_id, post.visibility).await
+}
+
+/// Send the new reactions counts of a post to the connections subscribed to it
+async fn notify_reactions_update(
+    app_state: &AppState,
+    event_tracker: &EventTracker,
+    post_id: i64,
+    visibility: PostVisibility,
+) -> Result<(), AppError> {
+    let reactions = get_reactions_counts(&app_state.pool, &[post_id])
+        .await?
+        .remove(&post_id)
+        .unwrap_or_default();
+
+    let event = WsEvent::new_post_reactions_update_event(post_id, &reactions);
+
+    event_tracker
+        .notify_post(
+            RealTimeEvent::PostReactionsUpdate { post_id },
+            visibility,
+            event.to_string(),
+        )
+        .await;
+
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    extractors::auth_extractor::AuthUser,
+    models::presence::{PresenceStatus, PresenceVisibility, PublicPresence},
+    utils::{
+        app_error::AppError,
+        real_time_event_management::{EventTracker, RealTimeEvent},
+    },
+    AppState,
+};
+
+/// Get the presence of an user, only for its followers and unless it hides it
+pub async fn get_presence_route(
+    AuthUser(auth_user): AuthUser,
+    Extension(event_tracker): Extension<EventTracker>,
+    Path(username): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<String, AppError> {
+    let account = sqlx::query!(
+        "SELECT id, last_seen_at, presence_hidden FROM account WHERE username = $1",
+        username
+    )
+    .fetch_optional(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting the presence of {username} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    let Some(account) = account else {
+        warn!("Cannot get the presence of user `{username}` that doesn't exist");
+        return Err(AppError::forbidden_error(Some(format!(
+            "L'utilisateur {username} n'existe pas."
+        ))));
+    };
+
+    let auth_user_id = auth_user.map(|auth_user| auth_user.id);
+    if auth_user_id != Some(account.id) {
+        if account.presence_hidden {
+            warn!("{username} hides its presence");
+            return Err(AppError::forbidden_error(Some(
+                "Cet utilisateur cache sa présence.",
+            )));
+        }
+
+        //Same restriction as the `presence_changed` WS event
+        let event = RealTimeEvent::PresenceChanged {
+            user_id: account.id,
+        };
+        if !event_tracker.can_subscribe(auth_user_id, &event).await {
+            warn!("{auth_user_id:?} tried to get the presence of {username} without following it");
+            return Err(AppError::forbidden_error(Some(
+                "Tu dois suivre cet utilisateur pour voir sa présence.",
+            )));
+        }
+    }
+
+    let status = event_tracker.presence_status(account.id).await;
+
+    let presence = PublicPresence {
+        status,
+        last_seen_at: account
+            .last_seen_at
+            .filter(|_| status == PresenceStatus::Offline),
+    };
+
+    Ok(json!(presence).to_string())
+}
+
+/// Hide or show the presence of the authenticated user to the other users
+pub async fn set_presence_visibility_route(
+    AuthUser(auth_user): AuthUser,
+    State(app_state): State<Arc<AppState>>,
+    Json(visibility): Json<PresenceVisibility>,
+) -> Result<(), AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    sqlx::query!(
+        "UPDATE account SET presence_hidden = $2 WHERE id = $1",
+        auth_user.id,
+        visibility.hidden
+    )
+    .execute(&app_state.pool)
+    .await
+    .map_err(|e| {
+        warn!(
+            "Error setting the presence visibility of {} : {e}",
+            auth_user.id
+        );
+        AppError::internal_server_error()
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use std::sync::Arc;
+
+use axum::extract::{Multipart, State};
+use tracing::warn;
+
+use crate::{
+    extractors::auth_extractor::AuthUser,
+    utils::{
+        app_error::AppError,
+        avatar::{AVATAR_SIZE, BANNER_HEIGHT, BANNER_WIDTH},
+        media::{process_profile_image, read_uploaded_file},
+    },
+    AppState,
+};
+
+#[derive(Clone, Copy)]
+enum ProfileImage {
+    Avatar,
+    Banner,
+}
+
+impl ProfileImage {
+    fn name(self) -> &'static str {
+        match self {
+            ProfileImage::Avatar => "avatar",
+            ProfileImage::Banner => "banner",
+        }
+    }
+
+    fn size(self) -> (u32, u32) {
+        match self {
+            ProfileImage::Avatar => (AVATAR_SIZE, AVATAR_SIZE),
+            ProfileImage::Banner => (BANNER_WIDTH, BANNER_HEIGHT),
+        }
+    }
+}
+
+/// Replace the avatar or the banner of an user, `None` removes it
+/// Returns the storage key of the previous image
+async fn set_profile_image(
+    app_state: &AppState,
+    user_id: i64,
+    kind: ProfileImage,
+    image: Option<(&str, &str)>,
+) -> Result<Option<String>, AppError> {
+    let (storage_key, url) = image.unzip();
+
+    let previous_storage_key = match kind {
+        ProfileImage::Avatar => sqlx::query_scalar!(
+            "UPDATE account SET avatar_storage_key = $1, avatar_url = $2 FROM (SELECT id, avatar_storage_key AS previous_storage_key FROM account WHERE id = $3 FOR UPDATE) AS previous WHERE account.id = previous.id RETURNING previous.previous_storage_key",
+            storage_key,
+            url,
+            user_id
+        )
+        .fetch_one(&app_state.pool)
+        .await,
+        ProfileImage::Banner => sqlx::query_scalar!(
+            "UPDATE account SET banner_storage_key = $1, banner_url = $2 FROM (SELECT id, banner_storage_key AS previous_storage_key FROM account WHERE id = $3 FOR UPDATE) AS previous WHERE account.id = previous.id RETURNING previous.previous_storage_key",
+            storage_key,
+            url,
+            user_id
+        )
+        .fetch_one(&app_state.pool)
+        .await,
+    }
+    .map_err(|e| {
+        warn!("Error setting {} of {user_id} : {e}", kind.name());
+        AppError::internal_server_error()
+    })?;
+
+    Ok(previous_storage_key)
+}
+
+async fn upload_profile_image(
+    app_state: &AppState,
+    user_id: i64,
+    kind: ProfileImage,
+    multipart: Multipart,
+) -> Result<String, AppError> {
+    let data = read_uploaded_file(multipart, user_id).await?;
+
+    let (width, height) = kind.size();
+
+    //Decoding and encoding images is CPU intensive
+    let image = tokio::task::spawn_blocking(move || process_profile_image(&data, width, height))
+        .await
+        .map_err(|e| {
+            warn!("Error processing {} of {user_id} : {e}", kind.name());
+            AppError::internal_server_error()
+        })??;
+
+    let storage_key = format!(
+        "{}-{:032x}.{}",
+        kind.name(),
+        rand::random::<u128>(),
+        image.extension
+    );
+
+    let storage = &app_state.media_storage;
+    let url = storage.save(&storage_key, image.data).await?;
+
+    let previous_storage_key =
+        match set_profile_image(app_state, user_id, kind, Some((&storage_key, &url))).await {
+            Ok(previous_storage_key) => previous_storage_key,
+            Err(e) => {
+                storage.delete(&storage_key).await.ok();
+                return Err(e);
+            }
+        };
+
+    if let Some(previous_storage_key) = previous_storage_key {
+        delete_previous_image(app_state, user_id, kind, &previous_storage_key).await;
+    }
+
+    Ok(url)
+}
+
+/// Delete the file of a profile image that has been replaced or removed
+/// The change is already saved, so a failure is only logged
+async fn delete_previous_image(
+    app_state: &AppState,
+    user_id: i64,
+    kind: ProfileImage,
+    previous_storage_key: &str,
+) {
+    if app_state
+        .media_storage
+        .delete(previous_storage_key)
+        .await
+        .is_err()
+    {
+        warn!(
+            "The previous {} `{previous_storage_key}` of {user_id} couldn't be deleted",
+            kind.name()
+        );
+    }
+}
+
+async fn delete_profile_image(
+    app_state: &AppState,
+    user_id: i64,
+    kind: ProfileImage,
+) -> Result<(), AppError> {
+    let Some(previous_storage_key) = set_profile_image(app_state, user_id, kind, None).await?
+    else {
+        warn!("{user_id} has no {} to delete", kind.name());
+        return Err(AppError::forbidden_error(Some(match kind {
+            ProfileImage::Avatar => "Tu n'as pas d'avatar.",
+            ProfileImage::Banner => "Tu n'as pas de bannière.",
+        })));
+    };
+
+    delete_previous_image(app_state, user_id, kind, &previous_storage_key).await;
+
+    Ok(())
+}
+
+pub async fn upload_avatar_route(
+    AuthUser(auth_user): AuthUser,
+    State(app_state): State<Arc<AppState>>,
+    multipart: Multipart,
+) -> Result<String, AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    upload_profile_image(&app_state, auth_user.id, ProfileImage::Avatar, multipart).await
+}
+
+pub async fn delete_avatar_route(
+    AuthUser(auth_user): AuthUser,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<(), AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    delete_profile_image(&app_state, auth_user.id, ProfileImage::Avatar).await
+}
+
+pub async fn upload_banner_route(
+    AuthUser(auth_user): AuthUser,
+    State(app_state): State<Arc<AppState>>,
+    multipart: Multipart,
+) -> Result<String, AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    upload_profile_image(&app_state, auth_user.id, ProfileImage::Banner, multipart).await
+}
+
+pub async fn delete_banner_route(
+    AuthUser(auth_user): AuthUser,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<(), AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    delete_profile_image(&app_state, auth_user.id, ProfileImage::Banner).await
+}
@@ -1,13 +1,16 @@
 use std::sync::Arc;
 
-use crate::models::account::AccountPermission;
-use crate::models::post::{NotificationPost, PublicPostAuthor};
-use crate::utils::post::check_new_post_data;
+use crate::utils::post::{
+    announce_new_post, attach_post_media, check_new_post_data, check_post_media,
+    get_post_publication, insert_post,
+};
 use crate::{
     extractors::auth_extractor::AuthUser,
+    models::{poll::NewPoll, post::PostVisibility},
     utils::{
         app_error::AppError,
-        real_time_event_management::{EventTracker, RealTimeEvent, WsEvent},
+        poll::{check_new_poll, save_poll},
+        real_time_event_management::{EventTracker, Users},
     },
     AppState,
 };
@@ -19,11 +22,25 @@ use tracing::warn;
 pub struct NewPost {
     pub title: String,
     pub content: String,
+    #[serde(default)]
+    pub visibility: PostVisibility,
+    /// Save the post without publishing it
+    #[serde(default)]
+    pub draft: bool,
+    /// Publish the post later, at this date
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub publish_at: Option<OffsetDateTime>,
+    /// The ids of the media uploaded with `/media`, in their order in the post
+    #[serde(default)]
+    pub media_ids: Vec<i64>,
+    #[serde(default)]
+    pub poll: Option<NewPoll>,
 }
 
 pub async fn publish_post_route(
     State(app_state): State<Arc<AppState>>,
     AuthUser(auth_user): AuthUser,
+    Extension(users): Extension<Users>,
     Extension(event_tracker): Extension<EventTracker>,
     Json(post): Json<NewPost>,
 ) -> Result<String, AppError> {
@@ -37,53 +54,58 @@ pub async fn publish_post_route(
 
     check_new_post_data(auth_user.id, title, content)?;
 
-    struct PostWithAuthorWrong {
-        id: i64,
-        title: String,
-        created_at: OffsetDateTime,
-        author_id: i64,
-        author_username: String,
-        author_permission: AccountPermission,
+    let publication = get_post_publication(auth_user.id, post.draft, post.publish_at)?;
+
+    check_post_media(&app_state.pool, auth_user.id, None, &post.media_ids).await?;
+
+    if let Some(poll) = &post.poll {
+        check_new_poll(auth_user.id, poll, publication)?;
     }
 
-    let post = match sqlx::query_file_as!(
-        PostWithAuthorWrong,
-        "./src/queries/insert_post.sql",
+    let internal_error = |e: sqlx::Error| {
+        warn!("Error publishing post of {} : {e}", auth_user.id);
+        AppError::internal_server_error()
+    };
+
+    //The post is only saved with all its media and its poll
+    let mut transaction = app_state.pool.begin().await.map_err(internal_error)?;
+
+    let new_post = insert_post(
+        &mut *transaction,
         auth_user.id,
         title,
         content,
+        None,
+        post.visibility,
+        publication,
     )
-    .fetch_one(&app_state.pool)
-    .await
-    {
-        Ok(post) => post,
-        Err(e) => {
-            warn!("Error inserting post with author {} : {e}", auth_user.id);
-            return Err(AppError::internal_server_error());
-        }
-    };
+    .await?;
 
-    let post = NotificationPost {
-        id: post.id,
-        title: post.title,
-        author: PublicPostAuthor {
-            id: post.author_id,
-            username: post.author_username,
-            permission: post.author_permission,
-        },
-        created_at: post.created_at,
-    };
+    if !post.media_ids.is_empty() {
+        attach_post_media(&mut transaction, new_post.id, &post.media_ids).await?;
+    }
 
-    let event = WsEvent::new_new_post_notification_event(&post);
+    if post.poll.is_some() {
+        save_poll(&mut transaction, new_post.id, post.poll.as_ref()).await?;
+    }
 
-    event_tracker
-        .notify(
-            RealTimeEvent::NewPostNotification {
-                followed_user_id: auth_user.id,
-            },
-            event.to_string(),
+    transaction.commit().await.map_err(internal_error)?;
+
+    //The post is saved, an error now would make the client publish it twice
+    if publication.is_published()
+        && announce_new_post(
+            &app_state.pool,
+            &users,
+            &event_tracker,
+            &new_post,
+            title,
+            content,
         )
-        .await;
+        .await
+        .is_err()
+    {
+        warn!("Post {} was published without being announced", new_post.id);
+    }
 
-    Ok(post.id.to_string())
+    Ok(new_post.id.to_string())
 }
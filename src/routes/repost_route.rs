@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{
+    extractors::auth_extractor::AuthUser,
+    models::post::{PostPublication, PostVisibility},
+    utils::{
+        app_error::AppError,
+        post::{
+            announce_new_post, check_new_post_data, get_notification_post, insert_post,
+            notify_new_post, PostEntities,
+        },
+        real_time_event_management::{EventTracker, Users},
+    },
+    AppState,
+};
+
+#[derive(Deserialize)]
+pub struct Quote {
+    pub title: String,
+    pub content: String,
+    #[serde(default)]
+    pub visibility: PostVisibility,
+}
+
+pub async fn repost_route(
+    State(app_state): State<Arc<AppState>>,
+    AuthUser(auth_user): AuthUser,
+    Extension(users): Extension<Users>,
+    Extension(event_tracker): Extension<EventTracker>,
+    Path(post_id): Path<i64>,
+    quote: Option<Json<Quote>>,
+) -> Result<String, AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("User not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    let Some(original_post) =
+        get_notification_post(&app_state.pool, post_id, Some(auth_user.id)).await?
+    else {
+        warn!("Cannot repost post {post_id} that doesn't exist");
+        return Err(AppError::forbidden_error(Some("Ce post n'existe pas.")));
+    };
+
+    //Reposting a followers-only post would show it to users that aren't followers of its author
+    if original_post.visibility == PostVisibility::Followers {
+        warn!("Cannot repost followers-only post {post_id}");
+        return Err(AppError::forbidden_error(Some(
+            "Ce post ne peut pas être repartagé.",
+        )));
+    }
+
+    //Reposting a repost (without title nor content) reposts the post it reposts
+    let original_post_id = match original_post.original_post_id {
+        Some(original_post_id) if original_post.title.is_empty() => original_post_id,
+        _ => original_post.id,
+    };
+
+    if let Some(Json(quote)) = quote {
+        let title = quote.title.trim();
+        let content = quote.content.trim();
+
+        check_new_post_data(auth_user.id, title, content)?;
+
+        let post = insert_post(
+            &app_state.pool,
+            auth_user.id,
+            title,
+            content,
+            Some(original_post_id),
+            //A quote embeds the original post, so it can't be more visible than it
+            quote.visibility.max(original_post.visibility),
+            PostPublication::Now,
+        )
+        .await?;
+
+        announce_new_post(
+            &app_state.pool,
+            &users,
+            &event_tracker,
+            &post,
+            title,
+            content,
+        )
+        .await?;
+
+        return Ok(post.id.to_string());
+    }
+
+    //The unique index on the reposts rejects a second repost of the same post
+    let post = insert_post(
+        &app_state.pool,
+        auth_user.id,
+        "",
+        "",
+        Some(original_post_id),
+        original_post.visibility,
+        PostPublication::Now,
+    )
+    .await?;
+
+    let entities = PostEntities {
+        tags: Vec::new(),
+        mentioned_users_ids: Vec::new(),
+    };
+
+    notify_new_post(&app_state.pool, &users, &event_tracker, &post, entities).await?;
+
+    Ok(post.id.to_string())
+}
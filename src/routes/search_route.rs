@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    extractors::auth_extractor::AuthUser,
+    models::{
+        account::{PublicAccount, PublicAccountRow},
+        post::PublicPost,
+    },
+    utils::{app_error::AppError, pagination::PaginationParams, post::get_public_posts},
+    AppState,
+};
+
+const MAX_SEARCH_QUERY_LENGTH: usize = 100;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchType {
+    Posts,
+    Users,
+}
+
+#[derive(Deserialize)]
+pub struct SearchParams {
+    pub q: String,
+    #[serde(rename = "type")]
+    pub search_type: Option<SearchType>,
+}
+
+/// The results of a search, each list is sorted by relevance
+#[derive(Serialize)]
+pub struct SearchResults {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub posts: Option<Vec<PublicPost>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub users: Option<Vec<PublicAccount>>,
+}
+
+pub async fn search_route(
+    AuthUser(auth_user): AuthUser,
+    Query(search_params): Query<SearchParams>,
+    Query(pagination_params): Query<PaginationParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<String, AppError> {
+    let query = search_params.q.trim().to_lowercase();
+
+    if query.is_empty() || query.chars().count() > MAX_SEARCH_QUERY_LENGTH {
+        warn!("Invalid search query length : `{query}`");
+        return Err(AppError::forbidden_error(Some(
+            "La recherche doit contenir entre 1 et 100 caractères.",
+        )));
+    }
+
+    let limit = pagination_params.limit();
+    let offset = pagination_params.offset();
+
+    let posts = if search_params.search_type == Some(SearchType::Users) {
+        None
+    } else {
+        let ids = sqlx::query_file_scalar!("./src/queries/search_posts.sql", query, limit, offset)
+            .fetch_all(&app_state.pool)
+            .await
+            .map_err(|e| {
+                warn!("Error searching posts with `{query}` : {e}");
+                AppError::internal_server_error()
+            })?;
+
+        Some(
+            get_public_posts(
+                &app_state.pool,
+                &ids,
+                auth_user.map(|auth_user| auth_user.id),
+            )
+            .await?,
+        )
+    };
+
+    let users = if search_params.search_type == Some(SearchType::Posts) {
+        None
+    } else {
+        let accounts = sqlx::query_file_as!(
+            PublicAccountRow,
+            "./src/queries/search_accounts.sql",
+            query,
+            limit,
+            offset
+        )
+        .fetch_all(&app_state.pool)
+        .await
+        .map_err(|e| {
+            warn!("Error searching users with `{query}` : {e}");
+            AppError::internal_server_error()
+        })?;
+
+        Some(accounts.into_iter().map(PublicAccount::from).collect())
+    };
+
+    Ok(json!(SearchResults { posts, users }).to_string())
+}
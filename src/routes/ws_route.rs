@@ -1,127 +1,230 @@
-use std::sync::{atomic::Ordering, Arc};
+use std::{
+    borrow::Cow,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
 
 use axum::{
     extract::{
-        ws::{Message, WebSocket},
-        State, WebSocketUpgrade,
+        ws::{CloseFrame, Message, WebSocket},
+        Query, State, WebSocketUpgrade,
     },
     response::Response,
     Extension,
 };
-
-use futures_util::StreamExt;
-use tokio::sync::RwLock;
+use axum_extra::extract::CookieJar;
+use futures_util::{
+    stream::{SplitSink, SplitStream},
+    SinkExt, StreamExt,
+};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+use tokio::{
+    sync::{oneshot, RwLock},
+    time::{interval, sleep_until, timeout_at, Instant},
+};
 use tracing::{info, warn};
 
 use crate::{
     extractors::auth_extractor::{AuthUser, InnerAuthUser},
+    models::account::AccountPermission,
     utils::{
+        app_error::AppError,
         authentification::authentificate,
-        real_time_event_management::{EventTracker, RealTimeEvent, UserConnection, Users, WsEvent},
+        notification::get_missed_events,
+        real_time_event_management::{
+            get_queue_metrics, EventTracker, HeartbeatConfig, RealTimeEvent, UserConnection, Users,
+            WsEvent,
+        },
+        ws_protocol::{
+            handle_client_request, ErrorCode, ProtocolError, ProtocolVersion, PROTOCOLS,
+        },
     },
     AppState, NEXT_NOT_CONNECTED_USER_ID,
 };
 
+/// Close code sent when the client fails to authenticate, with an invalid token or too many attempts
+pub const AUTHENTICATION_FAILED_CLOSE_CODE: u16 = 4001;
+/// Close code sent when the client doesn't authenticate in time
+pub const AUTHENTICATION_TIMEOUT_CLOSE_CODE: u16 = 4002;
+/// Close code sent when the authentication can't be checked because of a server error
+const INTERNAL_ERROR_CLOSE_CODE: u16 = 1011;
+
+const AUTHENTICATION_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_AUTHENTICATION_ATTEMPTS: usize = 3;
+
+#[derive(Deserialize)]
+pub struct WsParams {
+    pub token: Option<String>,
+    /// The id of the last event received by a reconnecting client, to get the events it missed
+    pub last_event_id: Option<i64>,
+}
+
 pub async fn ws_route(
     ws: WebSocketUpgrade,
-
+    Query(params): Query<WsParams>,
+    cookies: CookieJar,
     Extension(users): Extension<Users>,
     Extension(event_tracker): Extension<EventTracker>,
     State(app_state): State<Arc<AppState>>,
 ) -> Response {
-    ws.on_upgrade(|socket| handle_socket(socket, users, event_tracker, app_state))
+    let credentials = match params.token {
+        Some(token) => Credentials::Query(token),
+        None => match cookies.get("session") {
+            Some(cookie) => Credentials::Cookie(cookie.value().to_string()),
+            None => Credentials::None,
+        },
+    };
+
+    ws.protocols(PROTOCOLS).on_upgrade(move |socket| {
+        handle_socket(
+            socket,
+            credentials,
+            params.last_event_id,
+            users,
+            event_tracker,
+            app_state,
+        )
+    })
+}
+
+/// Get the number of messages waiting to be sent to the connections, only for the administrators
+pub async fn ws_metrics_route(
+    AuthUser(auth_user): AuthUser,
+    Extension(users): Extension<Users>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<String, AppError> {
+    let Some(auth_user) = auth_user else {
+        warn!("Not connected");
+        return Err(AppError::you_have_to_be_connected_to_perform_this_action_error());
+    };
+
+    let permission =
+        sqlx::query_scalar!("SELECT permission FROM account WHERE id = $1", auth_user.id)
+            .fetch_one(&app_state.pool)
+            .await
+            .map_err(|e| {
+                warn!("Error getting permission of {} : {e}", auth_user.id);
+                AppError::internal_server_error()
+            })?;
+
+    if !AccountPermission::from(permission).is_administrator() {
+        warn!(
+            "{} tried to get the WS metrics without being an administrator",
+            auth_user.id
+        );
+        return Err(AppError::forbidden_error(Some(
+            "Seuls les administrateurs peuvent voir les métriques des connexions.",
+        )));
+    }
+
+    Ok(json!(get_queue_metrics(&users).await).to_string())
+}
+
+/// The credentials sent with the upgrade request, before any message
+pub enum Credentials {
+    /// The `token` query parameter, the connection is closed if it is invalid
+    Query(String),
+    /// The `session` cookie, the client can still send a token if it is invalid
+    Cookie(String),
+    None,
+}
+
+enum AuthenticationError {
+    /// The client closed the connection
+    Closed,
+    Failed(u16, &'static str),
 }
 
 pub async fn handle_socket(
     socket: WebSocket,
-
+    credentials: Credentials,
+    last_event_id: Option<i64>,
     users: Users,
     event_tracker: EventTracker,
     app_state: Arc<AppState>,
 ) {
-    let (sender, mut receiver) = socket.split();
-
-    let AuthUser(auth_user) = loop {
-        let Some(msg) = receiver.next().await else {
-            warn!("Client didn't send the authentification token.");
-            continue;
-        };
-        let msg = match msg {
-            Ok(msg) => msg,
-            Err(e) => {
-                warn!("Client didn't send a valid message : {e}");
-                continue;
-            }
-        };
+    let version = ProtocolVersion::from_selected_protocol(socket.protocol());
+    let (mut sender, mut receiver) = socket.split();
 
-        let Message::Text(token) = msg else {
-            warn!("Client sent a non-text event.");
-            continue;
-        };
-
-        let auth_user = match authentificate(app_state.clone(), &token).await {
+    let auth_user =
+        match authenticate_connection(&mut sender, &mut receiver, credentials, version, &app_state)
+            .await
+        {
             Ok(auth_user) => auth_user,
-            Err(_) => {
-                warn!("Invalid credentials");
-                continue;
+            Err(AuthenticationError::Closed) => {
+                info!("Client closed the connection before authenticating");
+                return;
+            }
+            Err(AuthenticationError::Failed(code, reason)) => {
+                warn!("Closing the connection : {reason}");
+                if let Err(e) = sender
+                    .send(Message::Close(Some(CloseFrame {
+                        code,
+                        reason: Cow::Borrowed(reason),
+                    })))
+                    .await
+                {
+                    warn!("Error closing the connection : {e}");
+                }
+                return;
             }
         };
 
-        break auth_user;
+    let event =
+        WsEvent::new_authenticated_event(auth_user.as_ref().map(|auth_user| auth_user.id), version);
+    if let Err(e) = sender.send(Message::Text(event.to_string())).await {
+        warn!("Error sending the authentication acknowledgement : {e}");
+        return;
+    }
+
+    //Only the events of authenticated users are stored
+    let (replay_sender, replay) = match (&auth_user, last_event_id) {
+        (Some(_), Some(_)) => {
+            let (replay_sender, replay) = oneshot::channel();
+            (Some(replay_sender), Some(replay))
+        }
+        _ => (None, None),
     };
 
-    let user = Arc::new(RwLock::new(UserConnection::new(sender)));
+    let user = Arc::new(RwLock::new(UserConnection::new(
+        sender,
+        auth_user.as_ref().map(|auth_user| auth_user.id),
+        replay,
+    )));
 
     if let Some(auth_user) = auth_user {
         info!("User with id {} has connected.", auth_user.id);
-        let users_followed = match sqlx::query_as!(
-            InnerAuthUser,
-            r#"SELECT followed_id AS "id!" FROM follow where follower_id = $1"#,
-            auth_user.id
-        )
-        .fetch_all(&app_state.pool)
-        .await
-        {
-            Ok(users) => users,
-            Err(e) => {
-                warn!("{e}");
-                return;
-            }
-        };
-
-        //Perhaps do that asynchronously
-        for user_followed in &users_followed {
-            let event_type = RealTimeEvent::NewPostNotification {
-                followed_user_id: user_followed.id,
-            };
-            event_tracker.subscribe(event_type, user.clone()).await;
-        }
+        //The new posts of the followed users are sent by `send_followers_notification`
+        event_tracker
+            .subscribe(
+                RealTimeEvent::UserNotification {
+                    user_id: auth_user.id,
+                },
+                user.clone(),
+            )
+            .await;
 
         event_tracker
             .add_to_users(auth_user.id, users.clone(), user.clone())
             .await;
 
-        while let Some(msg) = receiver.next().await {
-            let Ok(msg) = msg else {
-                break;
-            };
-
-            if let Message::Text(text) = msg {
-                info!("{} sent the WS event `{text}`", auth_user.id);
-
-                if let Err(e) = event_tracker.handle_client_event(&text, user.clone()).await {
-                    if let Err(e) = user
-                        .write()
-                        .await
-                        .send_text_event(WsEvent::new_error(&e).to_string())
-                        .await
-                    {
-                        warn!("Error sending error to client : {e}");
-                    };
-                };
-            }
+        //The connection already receives the new events, so none is lost between the replay and the live events
+        if let (Some(replay_sender), Some(last_event_id)) = (replay_sender, last_event_id) {
+            replay_missed_events(&app_state.pool, auth_user.id, last_event_id, replay_sender).await;
         }
 
+        handle_messages(
+            auth_user.id,
+            version,
+            &mut receiver,
+            &user,
+            &event_tracker,
+            app_state.heartbeat,
+        )
+        .await;
+
         event_tracker.disconnect(auth_user.id, user, users).await;
     } else {
         let id = NEXT_NOT_CONNECTED_USER_ID.fetch_sub(1, Ordering::Relaxed);
@@ -130,29 +233,200 @@ pub async fn handle_socket(
 
         users.write().await.insert(id, vec![user.clone()]);
 
-        while let Some(msg) = receiver.next().await {
-            let Ok(msg) = msg else {
-                break;
-            };
-
-            if let Message::Text(text) = msg {
-                info!("Not connected user {id} sent the WS event `{text}`");
-
-                if let Err(e) = event_tracker.handle_client_event(&text, user.clone()).await {
-                    if let Err(e) = user
-                        .write()
-                        .await
-                        .send_text_event(WsEvent::new_error(&e).to_string())
-                        .await
-                    {
-                        warn!("Error sending error to client : {e}");
+        handle_messages(
+            id,
+            version,
+            &mut receiver,
+            &user,
+            &event_tracker,
+            app_state.heartbeat,
+        )
+        .await;
+
+        event_tracker.disconnect(id, user, users).await;
+
+        info!("Disconnected {id}");
+    }
+}
+
+/// Handle the messages of a connection until it is closed or stops answering the pings
+/// `id` is the id of the user, negative for a not connected user
+async fn handle_messages(
+    id: i64,
+    version: ProtocolVersion,
+    receiver: &mut SplitStream<WebSocket>,
+    user: &Arc<RwLock<UserConnection>>,
+    event_tracker: &EventTracker,
+    heartbeat: HeartbeatConfig,
+) {
+    let mut ping_interval = interval(heartbeat.ping_interval);
+    //The first tick completes immediately
+    ping_interval.tick().await;
+    let mut pong_deadline: Option<Instant> = None;
+    let mut closed = user.read().await.closed();
+
+    loop {
+        tokio::select! {
+            msg = receiver.next() => {
+                let Some(Ok(msg)) = msg else {
+                    break;
+                };
+
+                //Any message shows that the connection is alive
+                pong_deadline = None;
+
+                if let Message::Text(text) = msg {
+                    info!("{id} sent the WS event `{text}`");
+
+                    let reply = match version {
+                        ProtocolVersion::V1 => event_tracker
+                            .handle_client_event(&text, user.clone())
+                            .await
+                            .err()
+                            .map(|e| WsEvent::new_error(&e)),
+                        ProtocolVersion::V2 => {
+                            Some(handle_client_request(event_tracker, &text, user.clone()).await)
+                        }
                     };
+
+                    if let Some(reply) = reply {
+                        user.read().await.send_text_event(reply.to_string());
+                    }
                 }
             }
+            _ = ping_interval.tick() => {
+                user.read().await.send_ping();
+                pong_deadline.get_or_insert_with(|| Instant::now() + heartbeat.pong_timeout);
+            }
+            () = sleep_until(pong_deadline.unwrap_or_else(Instant::now)), if pong_deadline.is_some() => {
+                warn!("{id} didn't answer the ping in time");
+                break;
+            }
+            //The server closed the connection, because it was too slow
+            _ = closed.changed() => {
+                break;
+            }
         }
+    }
+}
 
-        event_tracker.disconnect(id, user, users).await;
+/// Send the events missed by a reconnecting client before its live events, followed by a `replayed` event
+async fn replay_missed_events(
+    pool: &PgPool,
+    user_id: i64,
+    last_event_id: i64,
+    replay_sender: oneshot::Sender<Vec<String>>,
+) {
+    //If the events can't be loaded, the sender is dropped and the live events are sent
+    let Ok((missed_events, truncated)) = get_missed_events(pool, user_id, last_event_id).await
+    else {
+        return;
+    };
 
-        info!("Disconnected {id}");
+    info!(
+        "Replaying {} events missed by {user_id} since {last_event_id}",
+        missed_events.len()
+    );
+
+    let replayed_event = WsEvent::new_replayed_event(missed_events.len(), truncated);
+    let events = missed_events
+        .iter()
+        .chain([&replayed_event])
+        .map(ToString::to_string)
+        .collect();
+
+    //The connection may have been closed in the meantime
+    let _ = replay_sender.send(events);
+}
+
+/// Authenticate a new connection, with the token of the upgrade request or with the first messages
+/// An empty message connects the client as a not connected user
+async fn authenticate_connection(
+    sender: &mut SplitSink<WebSocket, Message>,
+    receiver: &mut SplitStream<WebSocket>,
+    credentials: Credentials,
+    version: ProtocolVersion,
+    app_state: &Arc<AppState>,
+) -> Result<Option<InnerAuthUser>, AuthenticationError> {
+    let internal_error =
+        |_| AuthenticationError::Failed(INTERNAL_ERROR_CLOSE_CODE, "Erreur interne du serveur.");
+
+    match credentials {
+        Credentials::Query(token) => {
+            let AuthUser(auth_user) = authentificate(app_state.clone(), &token)
+                .await
+                .map_err(internal_error)?;
+            return auth_user.map(Some).ok_or(AuthenticationError::Failed(
+                AUTHENTICATION_FAILED_CLOSE_CODE,
+                "Token invalide.",
+            ));
+        }
+        Credentials::Cookie(token) => {
+            let AuthUser(auth_user) = authentificate(app_state.clone(), &token)
+                .await
+                .map_err(internal_error)?;
+            if auth_user.is_some() {
+                return Ok(auth_user);
+            }
+            info!("Client sent an invalid session cookie");
+        }
+        Credentials::None => {}
+    }
+
+    let deadline = Instant::now() + AUTHENTICATION_TIMEOUT;
+    let mut attempts = 0;
+
+    while attempts < MAX_AUTHENTICATION_ATTEMPTS {
+        let msg = match timeout_at(deadline, receiver.next()).await {
+            Ok(Some(Ok(msg))) => msg,
+            Ok(None | Some(Err(_))) => return Err(AuthenticationError::Closed),
+            Err(_) => {
+                return Err(AuthenticationError::Failed(
+                    AUTHENTICATION_TIMEOUT_CLOSE_CODE,
+                    "Authentification trop longue.",
+                ))
+            }
+        };
+
+        let (code, error) = match msg {
+            Message::Text(token) if token.is_empty() => return Ok(None),
+            Message::Text(token) => {
+                let AuthUser(auth_user) = authentificate(app_state.clone(), &token)
+                    .await
+                    .map_err(internal_error)?;
+                if auth_user.is_some() {
+                    return Ok(auth_user);
+                }
+                warn!("Invalid credentials");
+                (ErrorCode::InvalidToken, "Token invalide.")
+            }
+            Message::Binary(_) => {
+                warn!("Client sent a non-text event.");
+                (
+                    ErrorCode::InvalidMessage,
+                    "Le token doit être envoyé dans un message texte.",
+                )
+            }
+            Message::Close(_) => return Err(AuthenticationError::Closed),
+            //Pings are answered automatically
+            Message::Ping(_) | Message::Pong(_) => continue,
+        };
+
+        attempts += 1;
+
+        let event = match version {
+            ProtocolVersion::V1 => WsEvent::new_error(error),
+            ProtocolVersion::V2 => ProtocolError::new(code, error).to_event(None),
+        };
+
+        if let Err(e) = sender.send(Message::Text(event.to_string())).await {
+            warn!("Error sending error to client : {e}");
+            return Err(AuthenticationError::Closed);
+        }
     }
+
+    Err(AuthenticationError::Failed(
+        AUTHENTICATION_FAILED_CLOSE_CODE,
+        "Trop de tentatives d'authentification.",
+    ))
 }
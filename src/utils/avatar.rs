@@ -0,0 +1,63 @@
+use image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+use sha2::{Digest, Sha256};
+
+use crate::MEDIA_URL;
+
+use super::{app_error::AppError, media::encode_image};
+
+pub const AVATAR_SIZE: u32 = 400;
+pub const BANNER_WIDTH: u32 = 1500;
+pub const BANNER_HEIGHT: u32 = 500;
+
+const DEFAULT_AVATAR_PREFIX: &str = "default-avatar-";
+const IDENTICON_GRID_SIZE: u32 = 5;
+const IDENTICON_CELL_SIZE: u32 = 70;
+const IDENTICON_PADDING: u32 = 25;
+const IDENTICON_BACKGROUND: Rgb<u8> = Rgb([240, 240, 240]);
+
+/// The URL of the avatar of an user, its generated default avatar when it has not uploaded one
+pub fn get_avatar_url(user_id: i64, avatar_url: Option<String>) -> String {
+    avatar_url.unwrap_or_else(|| format!("{MEDIA_URL}/{DEFAULT_AVATAR_PREFIX}{user_id}.png"))
+}
+
+/// Get the id of the user from the storage key of a default avatar
+pub fn parse_default_avatar_key(key: &str) -> Option<i64> {
+    key.strip_prefix(DEFAULT_AVATAR_PREFIX)?
+        .strip_suffix(".png")?
+        .parse()
+        .ok()
+}
+
+/// Generate the default avatar of an user : a symmetric pattern whose shape and color only depend on its id
+pub fn generate_identicon(user_id: i64) -> Result<Vec<u8>, AppError> {
+    let hash = Sha256::digest(user_id.to_be_bytes());
+
+    //Keep the color away from white, the color of the background
+    let color = Rgb([hash[0] / 2 + 32, hash[1] / 2 + 32, hash[2] / 2 + 32]);
+
+    let size = IDENTICON_GRID_SIZE * IDENTICON_CELL_SIZE + 2 * IDENTICON_PADDING;
+    let mut image = RgbImage::from_pixel(size, size, IDENTICON_BACKGROUND);
+
+    //Only the left half (and the middle column) is generated, the right half mirrors it
+    let half_width = IDENTICON_GRID_SIZE.div_ceil(2);
+    for column in 0..half_width {
+        for row in 0..IDENTICON_GRID_SIZE {
+            let bit = column * IDENTICON_GRID_SIZE + row;
+            if hash[3 + (bit / 8) as usize] & (1 << (bit % 8)) == 0 {
+                continue;
+            }
+
+            for filled_column in [column, IDENTICON_GRID_SIZE - 1 - column] {
+                let x = IDENTICON_PADDING + filled_column * IDENTICON_CELL_SIZE;
+                let y = IDENTICON_PADDING + row * IDENTICON_CELL_SIZE;
+                for dx in 0..IDENTICON_CELL_SIZE {
+                    for dy in 0..IDENTICON_CELL_SIZE {
+                        image.put_pixel(x + dx, y + dy, color);
+                    }
+                }
+            }
+        }
+    }
+
+    encode_image(&DynamicImage::ImageRgb8(image), ImageOutputFormat::Png)
+}
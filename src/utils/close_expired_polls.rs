@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+use tracing::{info, warn};
+
+use crate::AppState;
+
+use super::{
+    app_error::AppError,
+    notification::send_notification,
+    poll::get_poll_results,
+    post::get_notification_post,
+    real_time_event_management::{EventTracker, RealTimeEvent, WsEvent},
+};
+
+struct ExpiredPoll {
+    post_id: i64,
+    author_id: i64,
+}
+
+/// Close the polls whose expiry date has passed, then send their results to their voters and subscribers
+pub async fn close_expired_polls(app_state: &AppState, event_tracker: &EventTracker) {
+    let mut interval = tokio::time::interval(Duration::from_secs(30));
+    loop {
+        interval.tick().await;
+        if let Ok(count) = close_due_polls(&app_state.pool, event_tracker).await {
+            if count > 0 {
+                info!("Closed {count} poll.s");
+            }
+        }
+    }
+}
+
+async fn close_due_polls(pool: &PgPool, event_tracker: &EventTracker) -> Result<usize, AppError> {
+    let polls = sqlx::query_as!(
+        ExpiredPoll,
+        "UPDATE poll SET closed = TRUE FROM post WHERE poll.post_id = post.id AND NOT poll.closed AND poll.expires_at <= NOW() RETURNING poll.post_id, post.author_id"
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error closing expired polls : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    for poll in &polls {
+        //A failed notification shouldn't prevent the voters of the other polls from being notified
+        if notify_poll_results(pool, event_tracker, poll)
+            .await
+            .is_err()
+        {
+            warn!(
+                "Cannot send the results of the poll of post {}",
+                poll.post_id
+            );
+        }
+    }
+
+    Ok(polls.len())
+}
+
+async fn notify_poll_results(
+    pool: &PgPool,
+    event_tracker: &EventTracker,
+    expired_poll: &ExpiredPoll,
+) -> Result<(), AppError> {
+    //Drafts can't be voted
+    let Some(post) =
+        get_notification_post(pool, expired_poll.post_id, Some(expired_poll.author_id)).await?
+    else {
+        return Ok(());
+    };
+
+    let results = get_poll_results(pool, expired_poll.post_id).await?;
+
+    event_tracker
+        .notify_post(
+            RealTimeEvent::PollUpdated {
+                post_id: expired_poll.post_id,
+            },
+            post.visibility,
+            WsEvent::new_poll_updated_event(&results).to_string(),
+        )
+        .await;
+
+    let voters_ids = sqlx::query_scalar!(
+        "SELECT account_id FROM poll_voter WHERE post_id = $1",
+        expired_poll.post_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        warn!(
+            "Error getting voters of the poll of post {} : {e}",
+            expired_poll.post_id
+        );
+        AppError::internal_server_error()
+    })?;
+
+    let event = WsEvent::new_poll_closed_event(&post, &results);
+
+    //The poll is already closed, so each voter has to be notified even if another one can't be
+    for voter_id in voters_ids {
+        if send_notification(pool, event_tracker, voter_id, &event)
+            .await
+            .is_err()
+        {
+            warn!(
+                "Cannot send the results of the poll of post {} to {voter_id}",
+                expired_poll.post_id
+            );
+        }
+    }
+
+    Ok(())
+}
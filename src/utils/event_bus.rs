@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{postgres::PgListener, PgPool};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::models::presence::PresenceStatus;
+
+use super::real_time_event_management::{RealTimeEvent, Recipient};
+
+/// The Postgres channel the instances of the API send their messages on
+const EVENTS_CHANNEL: &str = "apynext_events";
+/// Postgres limits the payload of a notification to 8000 bytes, bigger messages are stored in a table
+const MAX_NOTIFICATION_PAYLOAD: usize = 7900;
+
+/// A message sent to the other instances of the API
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BusMessage {
+    /// An event sent to the subscribers of a `RealTimeEvent`
+    Event {
+        event: RealTimeEvent,
+        content: String,
+    },
+    /// An event sent to all the connections of some users, with the id of each user's notification
+    Users {
+        recipients: Vec<Recipient>,
+        event: serde_json::Value,
+    },
+    /// The presence of an user on the instance that sent the message changed
+    Presence {
+        user_id: i64,
+        status: PresenceStatus,
+    },
+    /// The presence of all the users connected to the instance that sent the message
+    PresenceSnapshot {
+        statuses: Vec<(i64, PresenceStatus)>,
+    },
+}
+
+/// A message received from another instance
+pub struct ReceivedMessage {
+    /// A random id, generated by each instance when it starts
+    pub instance_id: u64,
+    pub message: BusMessage,
+}
+
+/// Relays the real-time events between the instances of the API
+/// Each instance delivers the events to its own connections
+#[async_trait]
+pub trait EventBus: Send + Sync {
+    /// Send a message to the other instances
+    async fn publish(&self, message: BusMessage);
+
+    /// Wait for the next message sent by another instance
+    async fn receive(&self) -> ReceivedMessage;
+}
+
+/// The bus of a single instance, there is no other instance to send the messages to
+pub struct MemoryEventBus;
+
+#[async_trait]
+impl EventBus for MemoryEventBus {
+    async fn publish(&self, _message: BusMessage) {}
+
+    async fn receive(&self) -> ReceivedMessage {
+        std::future::pending().await
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Payload {
+    Message(BusMessage),
+    /// The id of the message in the `event_bus_message` table
+    Stored(i64),
+}
+
+#[derive(Serialize, Deserialize)]
+struct Notification {
+    instance_id: u64,
+    payload: Payload,
+}
+
+/// Send the messages with Postgres `LISTEN/NOTIFY`, so several instances can share the same database
+/// The messages sent while an instance is disconnected from the database are lost
+pub struct PostgresEventBus {
+    pool: PgPool,
+    listener: Mutex<PgListener>,
+    instance_id: u64,
+}
+
+impl PostgresEventBus {
+    pub async fn new(pool: PgPool) -> Result<Self, sqlx::Error> {
+        let mut listener = PgListener::connect_with(&pool).await?;
+        listener.listen(EVENTS_CHANNEL).await?;
+
+        Ok(PostgresEventBus {
+            pool,
+            listener: Mutex::new(listener),
+            instance_id: rand::random(),
+        })
+    }
+
+    async fn send(&self, message: BusMessage) -> Result<(), sqlx::Error> {
+        let mut notification = json!(Notification {
+            instance_id: self.instance_id,
+            payload: Payload::Message(message),
+        })
+        .to_string();
+
+        if notification.len() > MAX_NOTIFICATION_PAYLOAD {
+            let id = sqlx::query_scalar!(
+                "INSERT INTO event_bus_message (content) VALUES ($1) RETURNING id",
+                notification
+            )
+            .fetch_one(&self.pool)
+            .await?;
+
+            //The other instances had enough time to load the old messages
+            sqlx::query!(
+                "DELETE FROM event_bus_message WHERE created_at < NOW() - INTERVAL '1 minute'"
+            )
+            .execute(&self.pool)
+            .await?;
+
+            notification = json!(Notification {
+                instance_id: self.instance_id,
+                payload: Payload::Stored(id),
+            })
+            .to_string();
+        }
+
+        sqlx::query!("SELECT pg_notify($1, $2)", EVENTS_CHANNEL, notification)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Parse a notification, `None` if it was sent by this instance or if it is invalid
+    async fn parse_notification(&self, payload: &str) -> Option<ReceivedMessage> {
+        let notification = match serde_json::from_str::<Notification>(payload) {
+            Ok(notification) => notification,
+            Err(e) => {
+                warn!("Invalid message received from another instance : {e}");
+                return None;
+            }
+        };
+
+        if notification.instance_id == self.instance_id {
+            return None;
+        }
+
+        let message = match notification.payload {
+            Payload::Message(message) => message,
+            Payload::Stored(id) => {
+                let content =
+                    sqlx::query_scalar!("SELECT content FROM event_bus_message WHERE id = $1", id)
+                        .fetch_optional(&self.pool)
+                        .await
+                        .map_err(|e| warn!("Error loading message {id} of another instance : {e}"))
+                        .ok()??;
+
+                let Ok(Notification {
+                    payload: Payload::Message(message),
+                    ..
+                }) = serde_json::from_str::<Notification>(&content)
+                else {
+                    warn!("Invalid stored message {id} received from another instance");
+                    return None;
+                };
+                message
+            }
+        };
+
+        Some(ReceivedMessage {
+            instance_id: notification.instance_id,
+            message,
+        })
+    }
+}
+
+#[async_trait]
+impl EventBus for PostgresEventBus {
+    async fn publish(&self, message: BusMessage) {
+        if let Err(e) = self.send(message).await {
+            warn!("Error sending a message to the other instances : {e}");
+        }
+    }
+
+    async fn receive(&self) -> ReceivedMessage {
+        let mut listener = self.listener.lock().await;
+        loop {
+            //The listener reconnects by itself when the connection is lost
+            let notification = match listener.recv().await {
+                Ok(notification) => notification,
+                Err(e) => {
+                    warn!("Error receiving a message from the other instances : {e}");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+
+            if let Some(message) = self.parse_notification(notification.payload()).await {
+                return message;
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashSet;
+
+use ammonia::Builder;
+use pulldown_cmark::{html, Event, Options, Parser};
+
+/// The tags produced by the supported Markdown subset : paragraphs, emphasis, code, lists, links and quotes
+const ALLOWED_TAGS: [&str; 12] = [
+    "p",
+    "br",
+    "em",
+    "strong",
+    "code",
+    "pre",
+    "ul",
+    "ol",
+    "li",
+    "a",
+    "blockquote",
+    "hr",
+];
+
+const ALLOWED_URL_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+/// Render the Markdown content of a post to sanitized HTML
+/// Raw HTML is dropped, the tags outside of the supported subset are removed but their text is kept
+/// and the links are only kept with an allowed URL scheme
+pub fn render_markdown(content: &str) -> String {
+    let parser = Parser::new_ext(content, Options::empty())
+        .filter(|event| !matches!(event, Event::Html(_) | Event::InlineHtml(_)));
+
+    let mut unsafe_html = String::with_capacity(content.len() * 3 / 2);
+    html::push_html(&mut unsafe_html, parser);
+
+    Builder::default()
+        .tags(HashSet::from(ALLOWED_TAGS))
+        .tag_attributes(
+            [
+                ("a", HashSet::from(["href"])),
+                ("ol", HashSet::from(["start"])),
+            ]
+            .into(),
+        )
+        .generic_attributes(HashSet::new())
+        .url_schemes(HashSet::from(ALLOWED_URL_SCHEMES))
+        .link_rel(Some("noopener noreferrer nofollow"))
+        .clean(&unsafe_html)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_markdown;
+
+    #[test]
+    fn renders_the_supported_subset() {
+        assert_eq!(
+            render_markdown("*em* **strong** `code`"),
+            "<p><em>em</em> <strong>strong</strong> <code>code</code></p>\n"
+        );
+    }
+
+    #[test]
+    fn keeps_the_links_with_an_allowed_scheme() {
+        assert_eq!(
+            render_markdown("[link](https://example.com)"),
+            "<p><a href=\"https://example.com\" rel=\"noopener noreferrer nofollow\">link</a></p>\n"
+        );
+        assert_eq!(
+            render_markdown("[mail](mailto:a@b.c)"),
+            "<p><a href=\"mailto:a@b.c\" rel=\"noopener noreferrer nofollow\">mail</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn removes_the_dangerous_urls() {
+        for content in [
+            "[a](javascript:alert(1))",
+            "[a](JaVaScRiPt:alert(1))",
+            "[a](data:text/html;base64,PHNjcmlwdD4=)",
+        ] {
+            assert_eq!(
+                render_markdown(content),
+                "<p><a rel=\"noopener noreferrer nofollow\">a</a></p>\n",
+                "{content}"
+            );
+        }
+    }
+
+    #[test]
+    fn removes_the_raw_html() {
+        assert_eq!(render_markdown("<script>alert(1)</script>"), "");
+        assert_eq!(render_markdown("<img src=x onerror=alert(1)>"), "");
+        assert_eq!(
+            render_markdown("text <script>alert(1)</script> end"),
+            "<p>text alert(1) end</p>\n"
+        );
+        assert_eq!(
+            render_markdown("a <b onclick=\"x\">bold</b> c"),
+            "<p>a bold c</p>\n"
+        );
+        assert_eq!(
+            render_markdown("<a href=\"https://x.y\" onclick=\"x\">raw</a>"),
+            "<p>raw</p>\n"
+        );
+    }
+
+    #[test]
+    fn removes_the_unsupported_elements() {
+        assert_eq!(
+            render_markdown("![alt](https://example.com/a.png)"),
+            "<p></p>\n"
+        );
+        assert_eq!(render_markdown("# Title"), "Title\n");
+    }
+
+    #[test]
+    fn overrides_the_rel_attribute() {
+        let html = render_markdown("[link](https://example.com \"title\")");
+        assert!(
+            html.contains("rel=\"noopener noreferrer nofollow\""),
+            "{html}"
+        );
+        assert!(!html.contains("title="), "{html}");
+    }
+}
@@ -0,0 +1,229 @@
+use std::io::Cursor;
+
+use axum::{body::Bytes, extract::Multipart};
+use image::{imageops::FilterType, io::Limits, DynamicImage, ImageFormat, ImageOutputFormat};
+use tracing::warn;
+
+use super::app_error::AppError;
+
+pub const MAX_MEDIA_SIZE: usize = 10 * 1024 * 1024;
+pub const MAX_POST_MEDIA: usize = 4;
+const MAX_MEDIA_DIMENSION: u32 = 8192;
+const THUMBNAIL_SIZE: u32 = 400;
+const JPEG_QUALITY: u8 = 85;
+
+/// An uploaded image, encoded again by the server
+pub struct EncodedImage {
+    pub content_type: &'static str,
+    pub extension: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+/// An uploaded image with its thumbnail
+pub struct ProcessedImage {
+    pub image: EncodedImage,
+    pub thumbnail: Vec<u8>,
+}
+
+/// Get the content type of a stored media file from its extension
+pub fn get_content_type(key: &str) -> Option<&'static str> {
+    match key.rsplit_once('.')?.1 {
+        "jpg" => Some("image/jpeg"),
+        "png" => Some("image/png"),
+        _ => None,
+    }
+}
+
+/// Read the `file` field of a multipart upload
+pub async fn read_uploaded_file(
+    mut multipart: Multipart,
+    auth_user_id: i64,
+) -> Result<Bytes, AppError> {
+    let too_large_error =
+        || AppError::forbidden_error(Some("Le fichier ne doit pas dépasser 10 Mo."));
+
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        warn!("Error reading multipart body of {auth_user_id} : {e}");
+        too_large_error()
+    })? {
+        if field.name() != Some("file") {
+            continue;
+        }
+
+        let data = field.bytes().await.map_err(|e| {
+            warn!("Error reading uploaded file of {auth_user_id} : {e}");
+            too_large_error()
+        })?;
+
+        if data.len() > MAX_MEDIA_SIZE {
+            warn!(
+                "{auth_user_id} tried to upload a file of {} bytes",
+                data.len()
+            );
+            return Err(too_large_error());
+        }
+
+        return Ok(data);
+    }
+
+    warn!("{auth_user_id} sent an upload without file");
+    Err(AppError::forbidden_error(Some(
+        "Aucun fichier n'a été envoyé.",
+    )))
+}
+
+pub fn encode_image(image: &DynamicImage, format: ImageOutputFormat) -> Result<Vec<u8>, AppError> {
+    let mut data = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut data), format)
+        .map_err(|e| {
+            warn!("Error encoding image : {e}");
+            AppError::internal_server_error()
+        })?;
+    Ok(data)
+}
+
+/// Decode an uploaded image, only JPEG, PNG, GIF and WebP images are accepted
+fn decode_image(data: &[u8]) -> Result<(DynamicImage, ImageFormat), AppError> {
+    let unsupported_format_error = || {
+        AppError::forbidden_error(Some(
+            "Seules les images JPEG, PNG, GIF et WebP sont acceptées.",
+        ))
+    };
+
+    let format = image::guess_format(data).map_err(|e| {
+        warn!("Unknown media format : {e}");
+        unsupported_format_error()
+    })?;
+
+    if !matches!(
+        format,
+        ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Gif | ImageFormat::WebP
+    ) {
+        warn!("Unsupported media format : {format:?}");
+        return Err(unsupported_format_error());
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_MEDIA_DIMENSION);
+    limits.max_image_height = Some(MAX_MEDIA_DIMENSION);
+
+    let mut reader = image::io::Reader::with_format(Cursor::new(data), format);
+    reader.limits(limits);
+
+    let image = reader.decode().map_err(|e| {
+        warn!("Error decoding {format:?} image : {e}");
+        AppError::forbidden_error(Some("Cette image est invalide ou trop grande."))
+    })?;
+
+    Ok((apply_exif_orientation(image, data), format))
+}
+
+/// Rotate an image as its EXIF orientation says, since encoding it again removes the orientation
+/// The cameras of the phones store the portrait photos in landscape with an orientation
+fn apply_exif_orientation(image: DynamicImage, data: &[u8]) -> DynamicImage {
+    let orientation = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(data))
+        .ok()
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?
+                .value
+                .get_uint(0)
+        });
+
+    match orientation {
+        Some(2) => image.fliph(),
+        Some(3) => image.rotate180(),
+        Some(4) => image.flipv(),
+        Some(5) => image.rotate90().fliph(),
+        Some(6) => image.rotate90(),
+        Some(7) => image.rotate270().fliph(),
+        Some(8) => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// Encode an image again, which strips the metadata (EXIF, GPS position...) of the uploaded file
+/// JPEG images stay JPEG images, the other formats are converted to PNG
+fn encode_uploaded_image(
+    image: &DynamicImage,
+    format: ImageFormat,
+) -> Result<EncodedImage, AppError> {
+    let (output_format, content_type, extension) = if format == ImageFormat::Jpeg {
+        (ImageOutputFormat::Jpeg(JPEG_QUALITY), "image/jpeg", "jpg")
+    } else {
+        (ImageOutputFormat::Png, "image/png", "png")
+    };
+
+    Ok(EncodedImage {
+        content_type,
+        extension,
+        width: image.width(),
+        height: image.height(),
+        data: encode_image(image, output_format)?,
+    })
+}
+
+/// Process an image uploaded as a media and generate its thumbnail
+pub fn process_image(data: &[u8]) -> Result<ProcessedImage, AppError> {
+    let (image, format) = decode_image(data)?;
+
+    let thumbnail = image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+
+    Ok(ProcessedImage {
+        image: encode_uploaded_image(&image, format)?,
+        thumbnail: encode_uploaded_image(&thumbnail, format)?.data,
+    })
+}
+
+/// Process an image uploaded as an avatar or a banner, it is cropped to fill the given size
+pub fn process_profile_image(
+    data: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<EncodedImage, AppError> {
+    let (image, format) = decode_image(data)?;
+
+    let image = image.resize_to_fill(width, height, FilterType::Lanczos3);
+
+    encode_uploaded_image(&image, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, ImageOutputFormat};
+
+    use super::decode_image;
+
+    /// A JPEG image of 4x2 pixels, with an EXIF orientation
+    fn jpeg_with_orientation(orientation: u8) -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        DynamicImage::new_rgb8(4, 2)
+            .write_to(&mut Cursor::new(&mut jpeg), ImageOutputFormat::Jpeg(90))
+            .unwrap();
+
+        //An APP1 segment with a TIFF header and an IFD containing only the orientation
+        let mut exif = b"Exif\0\0MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0".to_vec();
+        exif.extend([orientation, 0, 0, 0, 0, 0, 0]);
+        let length = u16::try_from(exif.len() + 2).unwrap().to_be_bytes();
+
+        let mut data = jpeg[..2].to_vec();
+        data.extend([0xFF, 0xE1, length[0], length[1]]);
+        data.extend(exif);
+        data.extend(&jpeg[2..]);
+        data
+    }
+
+    #[test]
+    fn applies_the_exif_orientation() {
+        let (image, _) = decode_image(&jpeg_with_orientation(1)).unwrap();
+        assert_eq!((image.width(), image.height()), (4, 2));
+
+        let (image, _) = decode_image(&jpeg_with_orientation(6)).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 4));
+    }
+}
@@ -0,0 +1,79 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use tracing::warn;
+
+use super::app_error::AppError;
+
+/// Where the uploaded media files are stored
+/// Keys are generated by the server, they only contain ASCII alphanumeric characters, `-` and `.`
+#[async_trait]
+pub trait MediaStorage: Send + Sync {
+    /// Store a file and return the public URL to get it
+    async fn save(&self, key: &str, data: Vec<u8>) -> Result<String, AppError>;
+
+    /// Get a stored file, `None` if it doesn't exist
+    async fn load(&self, key: &str) -> Result<Option<Vec<u8>>, AppError>;
+
+    async fn delete(&self, key: &str) -> Result<(), AppError>;
+}
+
+/// Check that a key can't be used to access files outside of the storage
+pub fn is_valid_storage_key(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with('.')
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
+
+/// Store the media files in a directory of the server, they are served by the `/media/files/:key` route
+pub struct LocalMediaStorage {
+    directory: PathBuf,
+    /// The URL of the `/media/files` route
+    base_url: String,
+}
+
+impl LocalMediaStorage {
+    pub fn new(directory: PathBuf, base_url: &str) -> Self {
+        LocalMediaStorage {
+            directory,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl MediaStorage for LocalMediaStorage {
+    async fn save(&self, key: &str, data: Vec<u8>) -> Result<String, AppError> {
+        tokio::fs::write(self.directory.join(key), data)
+            .await
+            .map_err(|e| {
+                warn!("Error saving media file {key} : {e}");
+                AppError::internal_server_error()
+            })?;
+
+        Ok(format!("{}/{key}", self.base_url))
+    }
+
+    async fn load(&self, key: &str) -> Result<Option<Vec<u8>>, AppError> {
+        match tokio::fs::read(self.directory.join(key)).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => {
+                warn!("Error loading media file {key} : {e}");
+                Err(AppError::internal_server_error())
+            }
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), AppError> {
+        match tokio::fs::remove_file(self.directory.join(key)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                warn!("Error deleting media file {key} : {e}");
+                Err(AppError::internal_server_error())
+            }
+            _ => Ok(()),
+        }
+    }
+}
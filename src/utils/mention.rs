@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+
+use crate::models::post::PostMention;
+
+use super::{register::check_username_rules, tag::find_prefixed_words};
+
+/// Extract the usernames `@mentioned` in a text, lowercased and without duplicates
+/// Words that can't be an username are ignored
+pub fn extract_mentions(text: &str) -> Vec<String> {
+    let mut usernames: Vec<String> = Vec::new();
+
+    for range in find_prefixed_words(text, '@') {
+        let username = text[range].to_lowercase();
+        if check_username_rules(&username).is_ok() && !usernames.contains(&username) {
+            usernames.push(username);
+        }
+    }
+
+    usernames
+}
+
+/// Find the position of the mentions of the given users in a text
+/// `users` maps the lowercased usernames to the users ids
+pub fn get_mention_entities(text: &str, users: &HashMap<String, i64>) -> Vec<PostMention> {
+    find_prefixed_words(text, '@')
+        .into_iter()
+        .filter_map(|range| {
+            let username = text[range.clone()].to_lowercase();
+            let user_id = *users.get(&username)?;
+            //Positions are in characters and include the `@`
+            let start = text[..range.start].chars().count() - 1;
+            let end = start + 1 + text[range].chars().count();
+            Some(PostMention {
+                user_id,
+                username,
+                start,
+                end,
+            })
+        })
+        .collect()
+}
@@ -1,8 +1,21 @@
 pub mod app_error;
 pub mod authentification;
+pub mod avatar;
+pub mod close_expired_polls;
 pub mod delete_not_activated_expired_accounts;
+pub mod event_bus;
+pub mod markdown;
+pub mod media;
+pub mod media_storage;
+pub mod mention;
+pub mod notification;
 pub mod pagination;
+pub mod poll;
 pub mod post;
+pub mod presence;
+pub mod publish_scheduled_posts;
 pub mod real_time_event_management;
 pub mod register;
+pub mod tag;
 pub mod token;
+pub mod ws_protocol;
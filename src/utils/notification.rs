@@ -0,0 +1,143 @@
+use serde_json::json;
+use sqlx::PgPool;
+use tracing::warn;
+
+use super::{
+    app_error::AppError,
+    real_time_event_management::{EventTracker, RealTimeEvent, Recipient, Users, WsEvent},
+};
+
+/// Store a notification for an user and send it to its connections
+/// `event` is a WS event created by `WsEvent`, its name is stored as the kind of the notification
+/// The id of the event is the next number of the sequence of the recipient, locked until the notification is committed
+pub async fn send_notification(
+    pool: &PgPool,
+    event_tracker: &EventTracker,
+    recipient_id: i64,
+    event: &serde_json::Value,
+) -> Result<(), AppError> {
+    let kind = event["event"].as_str().unwrap_or_default();
+
+    let id = sqlx::query_scalar!(
+        "WITH recipient AS (UPDATE account SET last_event_seq = last_event_seq + 1 WHERE id = $1 RETURNING last_event_seq) INSERT INTO notification (recipient_id, kind, content, event_seq) SELECT $1, $2, $3, last_event_seq FROM recipient RETURNING event_seq",
+        recipient_id,
+        kind,
+        event["content"]
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error inserting `{kind}` notification for {recipient_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    event_tracker
+        .notify(
+            RealTimeEvent::UserNotification {
+                user_id: recipient_id,
+            },
+            WsEvent::with_id(event, id).to_string(),
+        )
+        .await;
+
+    Ok(())
+}
+
+/// Store a notification for every follower of an user and send it to the followers that are connected
+/// The followers are locked in the order of their ids, so two concurrent notifications can't deadlock
+pub async fn send_followers_notification(
+    pool: &PgPool,
+    users: &Users,
+    event_tracker: &EventTracker,
+    followed_id: i64,
+    event: &serde_json::Value,
+) -> Result<(), AppError> {
+    let kind = event["event"].as_str().unwrap_or_default();
+
+    let recipients = sqlx::query_as!(
+        Recipient,
+        r#"WITH follower AS (SELECT account.id FROM account JOIN follow ON follow.follower_id = account.id WHERE follow.followed_id = $1 ORDER BY account.id FOR UPDATE OF account), recipient AS (UPDATE account SET last_event_seq = last_event_seq + 1 FROM follower WHERE account.id = follower.id RETURNING account.id, account.last_event_seq) INSERT INTO notification (recipient_id, kind, content, event_seq) SELECT id, $2, $3, last_event_seq FROM recipient RETURNING recipient_id AS "user_id", event_seq AS "event_id""#,
+        followed_id,
+        kind,
+        event["content"]
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error inserting `{kind}` notifications for the followers of {followed_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    event_tracker
+        .notify_users(users, recipients, event.clone())
+        .await;
+
+    Ok(())
+}
+
+/// The maximum number of events sent to a reconnecting client, it can get the older ones with `GET /notifications`
+pub const MAX_REPLAYED_EVENTS: usize = 100;
+
+struct MissedEvent {
+    id: i64,
+    kind: String,
+    content: serde_json::Value,
+}
+
+/// Get the events stored as notifications of an user after `last_event_id`, oldest first
+/// Also returns whether there are more missed events than `MAX_REPLAYED_EVENTS`, only the most recent ones are returned
+/// Only the notifications are replayed, the events of the subscriptions aren't stored
+pub async fn get_missed_events(
+    pool: &PgPool,
+    recipient_id: i64,
+    last_event_id: i64,
+) -> Result<(Vec<serde_json::Value>, bool), AppError> {
+    let mut missed_events = sqlx::query_as!(
+        MissedEvent,
+        "SELECT event_seq AS id, kind, content FROM notification WHERE recipient_id = $1 AND event_seq > $2 ORDER BY event_seq DESC LIMIT $3",
+        recipient_id,
+        last_event_id,
+        //One more to know if there are more missed events
+        i64::try_from(MAX_REPLAYED_EVENTS + 1).unwrap_or(i64::MAX)
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting the events missed by {recipient_id} since {last_event_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    let truncated = missed_events.len() > MAX_REPLAYED_EVENTS;
+    missed_events.truncate(MAX_REPLAYED_EVENTS);
+
+    let events = missed_events
+        .into_iter()
+        .rev()
+        .map(|missed_event| {
+            json!({
+                "event": missed_event.kind,
+                "content": missed_event.content,
+                "id": missed_event.id,
+            })
+        })
+        .collect();
+
+    Ok((events, truncated))
+}
+
+/// Get the number of unread notifications of an user
+pub async fn get_unread_notifications_count(
+    pool: &PgPool,
+    recipient_id: i64,
+) -> Result<i64, AppError> {
+    sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM notification WHERE recipient_id = $1 AND NOT read"#,
+        recipient_id
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error counting unread notifications of {recipient_id} : {e}");
+        AppError::internal_server_error()
+    })
+}
@@ -1,7 +1,61 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+/// The maximum number of items a client can ask for in one page
+pub const MAX_LIMIT: i64 = 100;
+
+/// The limit asked by a client, 10 by default or if it is negative, at most `MAX_LIMIT`
+pub fn clamp_limit(limit: Option<i64>) -> i64 {
+    match limit {
+        Some(limit) if !limit.is_negative() => limit.min(MAX_LIMIT),
+        _ => 10,
+    }
+}
 
 #[derive(Deserialize)]
 pub struct PaginationParams {
     pub offset: Option<i64>,
     pub limit: Option<i64>,
 }
+
+impl PaginationParams {
+    /// The limit asked by the client, 10 by default or if it is negative, at most `MAX_LIMIT`
+    pub fn limit(&self) -> i64 {
+        clamp_limit(self.limit)
+    }
+
+    /// The offset asked by the client, 0 by default or if it is negative
+    pub fn offset(&self) -> i64 {
+        self.offset.unwrap_or(0).max(0)
+    }
+}
+
+/// Pagination from the newest to the oldest items
+/// `before` is the `next_cursor` of the previous page, missing to get the first page
+#[derive(Deserialize)]
+pub struct CursorPaginationParams {
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+impl CursorPaginationParams {
+    /// The limit asked by the client, 10 by default or if it is negative, at most `MAX_LIMIT`
+    pub fn limit(&self) -> i64 {
+        clamp_limit(self.limit)
+    }
+}
+
+/// A page of items, `next_cursor` is `None` when there is no more items
+#[derive(Serialize)]
+pub struct CursorPage<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<i64>,
+}
+
+impl<T> CursorPage<T> {
+    /// Create a page from the items fetched with `limit`, `cursor` gives the cursor of an item
+    pub fn new(items: Vec<T>, limit: i64, cursor: impl Fn(&T) -> i64) -> Self {
+        let is_full = usize::try_from(limit).is_ok_and(|limit| limit != 0 && items.len() == limit);
+        let next_cursor = if is_full { items.last().map(cursor) } else { None };
+        CursorPage { items, next_cursor }
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::{HashMap, HashSet};
+
+use sqlx::{PgConnection, PgPool};
+use time::{Duration, OffsetDateTime};
+use tracing::{info, warn};
+
+use crate::models::{
+    poll::{NewPoll, PollOptionResult, PollResults, PublicPoll, PublicPollOption},
+    post::PostPublication,
+};
+
+use super::app_error::AppError;
+
+const MIN_POLL_OPTIONS: usize = 2;
+const MAX_POLL_OPTIONS: usize = 4;
+const MAX_POLL_OPTION_LENGTH: usize = 100;
+const MAX_POLL_DURATION: Duration = Duration::days(30);
+
+/// Check the poll of a new post, it must still be open when the post is published
+pub fn check_new_poll(
+    auth_user_id: i64,
+    poll: &NewPoll,
+    publication: PostPublication,
+) -> Result<(), AppError> {
+    if poll.options.len() < MIN_POLL_OPTIONS || poll.options.len() > MAX_POLL_OPTIONS {
+        warn!(
+            "User {auth_user_id} tried to create a poll with {} options",
+            poll.options.len()
+        );
+        return Err(AppError::forbidden_error(Some(
+            "Un sondage doit contenir entre 2 et 4 options.",
+        )));
+    }
+
+    let mut texts = HashSet::new();
+    for option in &poll.options {
+        let option = option.trim();
+        if option.is_empty() || option.chars().count() > MAX_POLL_OPTION_LENGTH {
+            warn!("User {auth_user_id} tried to create a poll option with a wrong length : `{option}`");
+            return Err(AppError::forbidden_error(Some(
+                "Une option de sondage doit contenir entre 1 et 100 caractères.",
+            )));
+        }
+
+        if !texts.insert(option) {
+            warn!("User {auth_user_id} tried to create a poll with the option `{option}` twice");
+            return Err(AppError::forbidden_error(Some(
+                "Les options d'un sondage doivent être différentes.",
+            )));
+        }
+    }
+
+    let published_at = publication
+        .publish_at()
+        .unwrap_or_else(OffsetDateTime::now_utc);
+
+    if poll.expires_at <= published_at || poll.expires_at - published_at > MAX_POLL_DURATION {
+        warn!(
+            "User {auth_user_id} tried to create a poll expiring at {} for a post published at {published_at}",
+            poll.expires_at
+        );
+        return Err(AppError::forbidden_error(Some(
+            "Un sondage doit se terminer après la publication du post et dans les 30 jours qui la suivent.",
+        )));
+    }
+
+    Ok(())
+}
+
+/// Replace the poll of a post, `None` removes it
+/// `connection` is the transaction of the post, so the post is never saved with a partial poll
+pub async fn save_poll(
+    connection: &mut PgConnection,
+    post_id: i64,
+    new_poll: Option<&NewPoll>,
+) -> Result<(), AppError> {
+    sqlx::query!("DELETE FROM poll WHERE post_id = $1", post_id)
+        .execute(&mut *connection)
+        .await
+        .map_err(|e| {
+            warn!("Error deleting poll of post {post_id} : {e}");
+            AppError::internal_server_error()
+        })?;
+
+    let Some(new_poll) = new_poll else {
+        return Ok(());
+    };
+
+    sqlx::query!(
+        "INSERT INTO poll (post_id, multiple_choice, expires_at) VALUES ($1, $2, $3)",
+        post_id,
+        new_poll.multiple_choice,
+        new_poll.expires_at
+    )
+    .execute(&mut *connection)
+    .await
+    .map_err(|e| {
+        warn!("Error inserting poll of post {post_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    let options: Vec<&str> = new_poll
+        .options
+        .iter()
+        .map(|option| option.trim())
+        .collect();
+
+    sqlx::query!(
+        "INSERT INTO poll_option (post_id, position, text) SELECT $1, option.position, option.text FROM UNNEST($2::VARCHAR[]) WITH ORDINALITY AS option(text, position)",
+        post_id,
+        &options as &[&str]
+    )
+    .execute(&mut *connection)
+    .await
+    .map_err(|e| {
+        warn!("Error inserting options of poll of post {post_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    Ok(())
+}
+
+/// Get the polls of posts, the results are only included for the polls closed or voted by `viewer_id`
+pub async fn get_public_polls(
+    pool: &PgPool,
+    ids: &[i64],
+    viewer_id: Option<i64>,
+) -> Result<HashMap<i64, PublicPoll>, AppError> {
+    struct PollOption {
+        post_id: i64,
+        multiple_choice: bool,
+        expires_at: OffsetDateTime,
+        closed: bool,
+        voters_count: i64,
+        id: i64,
+        text: String,
+        votes_count: i64,
+        my_vote: bool,
+    }
+
+    let options = sqlx::query_as!(
+        PollOption,
+        r#"SELECT poll.post_id, poll.multiple_choice, poll.expires_at, poll.closed OR poll.expires_at <= NOW() AS "closed!",
+        (SELECT COUNT(*) FROM poll_voter WHERE poll_voter.post_id = poll.post_id) AS "voters_count!",
+        poll_option.id, poll_option.text,
+        (SELECT COUNT(*) FROM poll_vote WHERE poll_vote.option_id = poll_option.id) AS "votes_count!",
+        EXISTS (SELECT 1 FROM poll_vote WHERE poll_vote.option_id = poll_option.id AND poll_vote.account_id = $2) AS "my_vote!"
+        FROM poll
+        JOIN poll_option ON poll_option.post_id = poll.post_id
+        WHERE poll.post_id = ANY($1)
+        ORDER BY poll_option.position"#,
+        ids,
+        viewer_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting polls of posts {ids:?} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    let mut polls: HashMap<i64, PublicPoll> = HashMap::new();
+
+    for option in options {
+        let public_poll = polls.entry(option.post_id).or_insert_with(|| PublicPoll {
+            options: Vec::new(),
+            multiple_choice: option.multiple_choice,
+            expires_at: option.expires_at,
+            closed: option.closed,
+            voters_count: option.voters_count,
+            my_votes: Vec::new(),
+        });
+
+        if option.my_vote {
+            public_poll.my_votes.push(option.id);
+        }
+
+        public_poll.options.push(PublicPollOption {
+            id: option.id,
+            text: option.text,
+            votes_count: Some(option.votes_count),
+        });
+    }
+
+    //The viewer has voted to a poll when they chose at least one of its options
+    for public_poll in polls.values_mut() {
+        if !public_poll.closed && public_poll.my_votes.is_empty() {
+            for option in &mut public_poll.options {
+                option.votes_count = None;
+            }
+        }
+    }
+
+    Ok(polls)
+}
+
+/// Get the results of a poll, the votes counts are only included once the poll is closed
+pub async fn get_poll_results(pool: &PgPool, post_id: i64) -> Result<PollResults, AppError> {
+    let poll_row = sqlx::query!(
+        r#"SELECT poll.closed OR poll.expires_at <= NOW() AS "closed!", (SELECT COUNT(*) FROM poll_voter WHERE poll_voter.post_id = poll.post_id) AS "voters_count!" FROM poll WHERE post_id = $1"#,
+        post_id
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting poll of post {post_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    let options = if poll_row.closed {
+        let options = sqlx::query_as!(
+            PollOptionResult,
+            r#"SELECT poll_option.id, (SELECT COUNT(*) FROM poll_vote WHERE poll_vote.option_id = poll_option.id) AS "votes_count!" FROM poll_option WHERE post_id = $1 ORDER BY position"#,
+            post_id
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| {
+            warn!("Error getting results of poll of post {post_id} : {e}");
+            AppError::internal_server_error()
+        })?;
+        Some(options)
+    } else {
+        None
+    };
+
+    Ok(PollResults {
+        post_id,
+        closed: poll_row.closed,
+        voters_count: poll_row.voters_count,
+        options,
+    })
+}
+
+/// Save the vote of an account to a poll, an account can only vote once
+pub async fn save_vote(
+    pool: &PgPool,
+    post_id: i64,
+    account_id: i64,
+    option_ids: &[i64],
+) -> Result<(), AppError> {
+    let internal_error = |e: sqlx::Error| {
+        warn!("Error saving vote of {account_id} to the poll of post {post_id} : {e}");
+        AppError::internal_server_error()
+    };
+
+    let mut transaction = pool.begin().await.map_err(internal_error)?;
+
+    let result = sqlx::query!(
+        "INSERT INTO poll_voter (post_id, account_id) VALUES ($1, $2) ON CONFLICT (post_id, account_id) DO NOTHING",
+        post_id,
+        account_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(internal_error)?;
+
+    if result.rows_affected() == 0 {
+        info!("{account_id} already voted to the poll of post {post_id}");
+        return Err(AppError::forbidden_error(Some(
+            "Tu as déjà voté à ce sondage.",
+        )));
+    }
+
+    sqlx::query!(
+        "INSERT INTO poll_vote (option_id, account_id) SELECT UNNEST($1::BIGINT[]), $2",
+        option_ids,
+        account_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(internal_error)?;
+
+    transaction.commit().await.map_err(internal_error)
+}
@@ -1,6 +1,32 @@
-use tracing::warn;
+use std::collections::{BTreeMap, HashMap};
 
-use super::app_error::AppError;
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::models::{
+    media::PublicMedia,
+    post::{
+        NotificationPost, NotificationPostWithAuthor, PostPublication, PostVisibility,
+        PostWithAuthor, PublicPost, PublicPostAuthor,
+    },
+    reaction::ReactionKind,
+};
+
+use super::{
+    app_error::AppError,
+    avatar::get_avatar_url,
+    markdown::render_markdown,
+    media::MAX_POST_MEDIA,
+    mention::{extract_mentions, get_mention_entities},
+    notification::{send_followers_notification, send_notification},
+    poll::get_public_polls,
+    real_time_event_management::{EventTracker, RealTimeEvent, Users, WsEvent},
+    tag::extract_hashtags,
+};
+
+/// The unique index that prevents an user from reposting a post twice
+const REPOST_INDEX: &str = "post_repost_idx";
 
 pub fn check_new_post_data(auth_user_id: i64, title: &str, content: &str) -> Result<(), AppError> {
     if title.len() < 3 || title.len() > 50 {
@@ -27,3 +53,616 @@ pub fn check_new_post_data(auth_user_id: i64, title: &str, content: &str) -> Res
 
     Ok(())
 }
+
+/// Get when a new post is published from the `draft` and `publish_at` fields of the request
+pub fn get_post_publication(
+    auth_user_id: i64,
+    draft: bool,
+    publish_at: Option<OffsetDateTime>,
+) -> Result<PostPublication, AppError> {
+    match (draft, publish_at) {
+        (false, None) => Ok(PostPublication::Now),
+        (true, None) => Ok(PostPublication::Draft),
+        (true, Some(_)) => {
+            warn!("User {auth_user_id} tried to create a draft with a publication date");
+            Err(AppError::forbidden_error(Some(
+                "Un brouillon ne peut pas avoir de date de publication.",
+            )))
+        }
+        (false, Some(publish_at)) if publish_at <= OffsetDateTime::now_utc() => {
+            warn!("User {auth_user_id} tried to schedule a post in the past : {publish_at}");
+            Err(AppError::forbidden_error(Some(
+                "La date de publication doit être dans le futur.",
+            )))
+        }
+        (false, Some(publish_at)) => Ok(PostPublication::Scheduled(publish_at)),
+    }
+}
+
+pub fn check_new_comment_data(auth_user_id: i64, content: &str) -> Result<(), AppError> {
+    if content.is_empty() || content.len() > 1000 {
+        warn!(
+            "User {} tried to create a comment with a content with a wrong length : {}/1000",
+            auth_user_id,
+            content.len()
+        );
+        return Err(AppError::forbidden_error(Some(
+            "Le contenu d'un commentaire doit contenir entre 1 et 1 000 caractères.",
+        )));
+    }
+
+    Ok(())
+}
+
+/// Insert a new post, `original_post_id` is the id of the post reposted or quoted
+/// The post has to be announced with `announce_new_post` once published, after the commit of its transaction
+pub async fn insert_post(
+    executor: impl PgExecutor<'_>,
+    author_id: i64,
+    title: &str,
+    content: &str,
+    original_post_id: Option<i64>,
+    visibility: PostVisibility,
+    publication: PostPublication,
+) -> Result<NotificationPost, AppError> {
+    let post = sqlx::query_file_as!(
+        NotificationPostWithAuthor,
+        "./src/queries/insert_post.sql",
+        author_id,
+        title,
+        content,
+        original_post_id,
+        visibility as i32,
+        publication.is_published(),
+        publication.publish_at()
+    )
+    .fetch_one(executor)
+    .await
+    .map_err(|e| match &e {
+        sqlx::Error::Database(db_error) if db_error.constraint() == Some(REPOST_INDEX) => {
+            info!("{author_id} already reposted {original_post_id:?}");
+            AppError::forbidden_error(Some("Tu as déjà repartagé ce post."))
+        }
+        _ => {
+            warn!("Error inserting post with author {author_id} : {e}");
+            AppError::internal_server_error()
+        }
+    })?;
+
+    Ok(post.into())
+}
+
+/// Check that media can be attached to a post : they have been uploaded by its author and they aren't attached to another post
+/// `post_id` is `None` for a post that doesn't exist yet
+pub async fn check_post_media(
+    pool: &PgPool,
+    author_id: i64,
+    post_id: Option<i64>,
+    media_ids: &[i64],
+) -> Result<(), AppError> {
+    if media_ids.is_empty() {
+        return Ok(());
+    }
+
+    if media_ids.len() > MAX_POST_MEDIA {
+        warn!(
+            "User {author_id} tried to attach {} media to a post",
+            media_ids.len()
+        );
+        return Err(AppError::forbidden_error(Some(
+            "Un post ne peut pas contenir plus de 4 médias.",
+        )));
+    }
+
+    let available_media_count = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM media WHERE id = ANY($1) AND owner_id = $2 AND (post_id IS NULL OR post_id = $3)"#,
+        media_ids,
+        author_id,
+        post_id
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error checking media {media_ids:?} of {author_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    //Duplicated ids are only counted once
+    if usize::try_from(available_media_count).ok() != Some(media_ids.len()) {
+        warn!("User {author_id} tried to attach unavailable media {media_ids:?} to a post");
+        return Err(AppError::forbidden_error(Some(
+            "Un des médias n'existe pas ou est déjà utilisé.",
+        )));
+    }
+
+    Ok(())
+}
+
+/// Replace the media attached to a post, they must have been checked with `check_post_media`
+/// `connection` is the transaction of the post
+pub async fn attach_post_media(
+    connection: &mut PgConnection,
+    post_id: i64,
+    media_ids: &[i64],
+) -> Result<(), AppError> {
+    sqlx::query!(
+        "UPDATE media SET post_id = NULL WHERE post_id = $1 AND id <> ALL($2)",
+        post_id,
+        media_ids
+    )
+    .execute(&mut *connection)
+    .await
+    .map_err(|e| {
+        warn!("Error detaching media of post {post_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    if media_ids.is_empty() {
+        return Ok(());
+    }
+
+    //A media attached to another post since it was checked stays attached to it
+    let result = sqlx::query!(
+        "UPDATE media SET post_id = $1, position = array_position($2, id) WHERE id = ANY($2) AND (post_id IS NULL OR post_id = $1)",
+        post_id,
+        media_ids
+    )
+    .execute(&mut *connection)
+    .await
+    .map_err(|e| {
+        warn!("Error attaching media {media_ids:?} to post {post_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    if usize::try_from(result.rows_affected()).ok() != Some(media_ids.len()) {
+        warn!("Some media {media_ids:?} were attached to another post before post {post_id}");
+    }
+
+    Ok(())
+}
+
+/// The tags of a post and the ids of the users it mentions
+pub struct PostEntities {
+    pub tags: Vec<String>,
+    pub mentioned_users_ids: Vec<i64>,
+}
+
+/// Extract and save the tags and the mentions of a new post
+pub async fn save_post_entities(
+    pool: &PgPool,
+    post_id: i64,
+    title: &str,
+    content: &str,
+) -> Result<PostEntities, AppError> {
+    let mut tags = extract_hashtags(title);
+    for tag in extract_hashtags(content) {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+
+    if !tags.is_empty() {
+        sqlx::query!(
+            "INSERT INTO post_tag (post_id, tag) SELECT $1, UNNEST($2::VARCHAR[])",
+            post_id,
+            &tags
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| {
+            warn!("Error inserting tags {tags:?} of post {post_id} : {e}");
+            AppError::internal_server_error()
+        })?;
+    }
+
+    let usernames = extract_mentions(content);
+
+    let mentioned_users_ids = if usernames.is_empty() {
+        Vec::new()
+    } else {
+        sqlx::query_scalar!(
+            "WITH mentioned_account AS (SELECT id FROM account WHERE username = ANY($2) AND email_verified = TRUE) INSERT INTO post_mention (post_id, account_id) SELECT $1, id FROM mentioned_account RETURNING account_id",
+            post_id,
+            &usernames
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| {
+            warn!("Error inserting mentions {usernames:?} of post {post_id} : {e}");
+            AppError::internal_server_error()
+        })?
+    };
+
+    Ok(PostEntities {
+        tags,
+        mentioned_users_ids,
+    })
+}
+
+/// Save the entities of a post that has just been published and notify the users concerned
+pub async fn announce_new_post(
+    pool: &PgPool,
+    users: &Users,
+    event_tracker: &EventTracker,
+    post: &NotificationPost,
+    title: &str,
+    content: &str,
+) -> Result<(), AppError> {
+    let entities = save_post_entities(pool, post.id, title, content).await?;
+    notify_new_post(pool, users, event_tracker, post, entities).await
+}
+
+/// Notify the followers of the author of a new post, the users it mentions and the subscribers of its tags
+/// Only the users that can see the post are notified
+pub async fn notify_new_post(
+    pool: &PgPool,
+    users: &Users,
+    event_tracker: &EventTracker,
+    post: &NotificationPost,
+    entities: PostEntities,
+) -> Result<(), AppError> {
+    let event = WsEvent::new_new_post_notification_event(post);
+
+    //Unlisted posts aren't sent to the followers
+    if post.visibility != PostVisibility::Unlisted {
+        send_followers_notification(pool, users, event_tracker, post.author.id, &event).await?;
+    }
+
+    let mut mentioned_users_ids = entities.mentioned_users_ids;
+    mentioned_users_ids.retain(|user_id| *user_id != post.author.id);
+
+    if post.visibility == PostVisibility::Followers && !mentioned_users_ids.is_empty() {
+        mentioned_users_ids = sqlx::query_scalar!(
+            "SELECT follower_id FROM follow WHERE followed_id = $1 AND follower_id = ANY($2)",
+            post.author.id,
+            &mentioned_users_ids
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| {
+            warn!(
+                "Error getting the followers of {} among {mentioned_users_ids:?} : {e}",
+                post.author.id
+            );
+            AppError::internal_server_error()
+        })?;
+    }
+
+    let event = WsEvent::new_mention_event(post);
+
+    for user_id in mentioned_users_ids {
+        send_notification(pool, event_tracker, user_id, &event).await?;
+    }
+
+    event_tracker
+        .notify_post(
+            RealTimeEvent::UserPosts {
+                user_id: post.author.id,
+            },
+            post.visibility,
+            WsEvent::new_user_post_event(post).to_string(),
+        )
+        .await;
+
+    for tag in entities.tags {
+        let event = WsEvent::new_new_post_with_tag_event(&tag, post);
+        event_tracker
+            .notify_post(
+                RealTimeEvent::NewPostWithTag { tag },
+                post.visibility,
+                event.to_string(),
+            )
+            .await;
+    }
+
+    Ok(())
+}
+
+/// Get the posts with the given ids, in the same order as `ids`, with the posts they repost embedded
+/// Ids of posts that don't exist or that `viewer_id` can't see are skipped
+/// `viewer_id` is the id of the authenticated user the posts are sent to, if any
+pub async fn get_public_posts(
+    pool: &PgPool,
+    ids: &[i64],
+    viewer_id: Option<i64>,
+) -> Result<Vec<PublicPost>, AppError> {
+    let mut posts = get_public_posts_by_id(pool, ids, viewer_id).await?;
+
+    let mut original_posts_ids: Vec<i64> = posts
+        .values()
+        .filter_map(|post| post.original_post_id)
+        .collect();
+    original_posts_ids.sort_unstable();
+    original_posts_ids.dedup();
+
+    if !original_posts_ids.is_empty() {
+        let original_posts = get_public_posts_by_id(pool, &original_posts_ids, viewer_id).await?;
+        for post in posts.values_mut() {
+            post.original_post = post
+                .original_post_id
+                .and_then(|id| original_posts.get(&id))
+                .map(|original_post| Box::new(original_post.clone()));
+        }
+    }
+
+    Ok(ids.iter().filter_map(|id| posts.remove(id)).collect())
+}
+
+async fn get_public_posts_by_id(
+    pool: &PgPool,
+    ids: &[i64],
+    viewer_id: Option<i64>,
+) -> Result<HashMap<i64, PublicPost>, AppError> {
+    let posts = sqlx::query_file_as!(
+        PostWithAuthor,
+        "./src/queries/select_posts_by_ids.sql",
+        ids,
+        viewer_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting posts {ids:?} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    let mut posts: HashMap<i64, PublicPost> = posts
+        .into_iter()
+        .map(|post| {
+            let mut post = PublicPost::from(post);
+            post.content_html = render_markdown(&post.content);
+            (post.id, post)
+        })
+        .collect();
+
+    add_media(pool, ids, &mut posts).await?;
+    add_polls(pool, ids, viewer_id, &mut posts).await?;
+    add_mentions(pool, ids, &mut posts).await?;
+    add_reactions(pool, ids, viewer_id, &mut posts).await?;
+    if let Some(viewer_id) = viewer_id {
+        add_bookmarks(pool, ids, viewer_id, &mut posts).await?;
+    }
+
+    Ok(posts)
+}
+
+async fn add_media(
+    pool: &PgPool,
+    ids: &[i64],
+    posts: &mut HashMap<i64, PublicPost>,
+) -> Result<(), AppError> {
+    struct PostMedia {
+        post_id: Option<i64>,
+        id: i64,
+        content_type: String,
+        width: i32,
+        height: i32,
+        url: String,
+        thumbnail_url: String,
+    }
+
+    let media = sqlx::query_as!(
+        PostMedia,
+        "SELECT post_id, id, content_type, width, height, url, thumbnail_url FROM media WHERE post_id = ANY($1) ORDER BY position",
+        ids
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting media of posts {ids:?} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    for media in media {
+        let Some(post) = media.post_id.and_then(|post_id| posts.get_mut(&post_id)) else {
+            continue;
+        };
+
+        post.media.push(PublicMedia {
+            id: media.id,
+            content_type: media.content_type,
+            width: media.width,
+            height: media.height,
+            url: media.url,
+            thumbnail_url: media.thumbnail_url,
+        });
+    }
+
+    Ok(())
+}
+
+async fn add_polls(
+    pool: &PgPool,
+    ids: &[i64],
+    viewer_id: Option<i64>,
+    posts: &mut HashMap<i64, PublicPost>,
+) -> Result<(), AppError> {
+    for (post_id, poll) in get_public_polls(pool, ids, viewer_id).await? {
+        if let Some(post) = posts.get_mut(&post_id) {
+            post.poll = Some(poll);
+        }
+    }
+
+    Ok(())
+}
+
+async fn add_mentions(
+    pool: &PgPool,
+    ids: &[i64],
+    posts: &mut HashMap<i64, PublicPost>,
+) -> Result<(), AppError> {
+    struct MentionedUser {
+        post_id: i64,
+        id: i64,
+        username: String,
+    }
+
+    let mentioned_users = sqlx::query_as!(
+        MentionedUser,
+        "SELECT post_mention.post_id, account.id, account.username FROM post_mention JOIN account ON post_mention.account_id = account.id WHERE post_mention.post_id = ANY($1)",
+        ids
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting users mentioned in posts {ids:?} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    let mut mentioned_users_by_post: HashMap<i64, HashMap<String, i64>> = HashMap::new();
+    for user in mentioned_users {
+        mentioned_users_by_post
+            .entry(user.post_id)
+            .or_default()
+            .insert(user.username, user.id);
+    }
+
+    for (post_id, users) in mentioned_users_by_post {
+        if let Some(post) = posts.get_mut(&post_id) {
+            post.mentions = get_mention_entities(&post.content, &users);
+        }
+    }
+
+    Ok(())
+}
+
+async fn add_reactions(
+    pool: &PgPool,
+    ids: &[i64],
+    viewer_id: Option<i64>,
+    posts: &mut HashMap<i64, PublicPost>,
+) -> Result<(), AppError> {
+    for (post_id, reactions) in get_reactions_counts(pool, ids).await? {
+        if let Some(post) = posts.get_mut(&post_id) {
+            post.reactions = reactions;
+        }
+    }
+
+    let Some(viewer_id) = viewer_id else {
+        return Ok(());
+    };
+
+    let my_reactions = sqlx::query!(
+        "SELECT post_id, kind FROM reaction WHERE account_id = $1 AND post_id = ANY($2)",
+        viewer_id,
+        ids
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting reactions of {viewer_id} to posts {ids:?} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    for reaction in my_reactions {
+        if let Some(post) = posts.get_mut(&reaction.post_id) {
+            post.my_reaction = ReactionKind::from_name(&reaction.kind);
+        }
+    }
+
+    Ok(())
+}
+
+async fn add_bookmarks(
+    pool: &PgPool,
+    ids: &[i64],
+    viewer_id: i64,
+    posts: &mut HashMap<i64, PublicPost>,
+) -> Result<(), AppError> {
+    let bookmarked_posts_ids = sqlx::query_scalar!(
+        "SELECT post_id FROM bookmark WHERE account_id = $1 AND post_id = ANY($2)",
+        viewer_id,
+        ids
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting bookmarks of {viewer_id} for posts {ids:?} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    for post_id in bookmarked_posts_ids {
+        if let Some(post) = posts.get_mut(&post_id) {
+            post.bookmarked = true;
+        }
+    }
+
+    Ok(())
+}
+
+/// Count the reactions of each kind for the given posts
+/// Posts without reactions are missing from the returned map
+pub async fn get_reactions_counts(
+    pool: &PgPool,
+    ids: &[i64],
+) -> Result<HashMap<i64, BTreeMap<ReactionKind, i64>>, AppError> {
+    let counts = sqlx::query!(
+        r#"SELECT post_id, kind, COUNT(*) AS "total!" FROM reaction WHERE post_id = ANY($1) GROUP BY post_id, kind"#,
+        ids
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error counting reactions of posts {ids:?} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    let mut reactions: HashMap<i64, BTreeMap<ReactionKind, i64>> = HashMap::new();
+    for count in counts {
+        if let Some(kind) = ReactionKind::from_name(&count.kind) {
+            reactions
+                .entry(count.post_id)
+                .or_default()
+                .insert(kind, count.total);
+        }
+    }
+
+    Ok(reactions)
+}
+
+/// Get a post in the format sent in the notifications, `None` if it doesn't exist or if `viewer_id` can't see it
+pub async fn get_notification_post(
+    pool: &PgPool,
+    post_id: i64,
+    viewer_id: Option<i64>,
+) -> Result<Option<NotificationPost>, AppError> {
+    let post = sqlx::query_file_as!(
+        NotificationPostWithAuthor,
+        "./src/queries/select_notification_post.sql",
+        post_id,
+        viewer_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting post {post_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    Ok(post.map(NotificationPost::from))
+}
+
+/// Get an user in the format used for the authors of posts
+pub async fn get_public_post_author(
+    pool: &PgPool,
+    user_id: i64,
+) -> Result<PublicPostAuthor, AppError> {
+    let user = sqlx::query!(
+        "SELECT id, username, permission, avatar_url, banner_url FROM account WHERE id = $1",
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error getting user {user_id} : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    Ok(PublicPostAuthor {
+        id: user.id,
+        username: user.username,
+        permission: user.permission.into(),
+        avatar_url: get_avatar_url(user.id, user.avatar_url),
+        banner_url: user.banner_url,
+    })
+}
@@ -0,0 +1,208 @@
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    sync::Arc,
+};
+
+use sqlx::PgPool;
+use tokio::sync::RwLock;
+use tracing::warn;
+
+use crate::models::presence::PresenceStatus;
+
+/// The presence of the users connected to this instance and to the other instances of the API
+#[derive(Clone)]
+pub struct Presence {
+    pool: PgPool,
+    /// The status of the users connected to this instance
+    local: Arc<RwLock<HashMap<i64, PresenceStatus>>>,
+    /// The status of the users connected to the other instances, by instance
+    remote: Arc<RwLock<HashMap<u64, HashMap<i64, PresenceStatus>>>>,
+}
+
+impl Presence {
+    pub fn new(pool: PgPool) -> Self {
+        Presence {
+            pool,
+            local: Arc::default(),
+            remote: Arc::default(),
+        }
+    }
+
+    /// The status of an user on all the instances, the most present status of its connections
+    pub async fn status(&self, user_id: i64) -> PresenceStatus {
+        let local_status = self
+            .local
+            .read()
+            .await
+            .get(&user_id)
+            .copied()
+            .unwrap_or(PresenceStatus::Offline);
+
+        local_status.max(self.remote_status(user_id).await)
+    }
+
+    /// The status of an user on the other instances
+    async fn remote_status(&self, user_id: i64) -> PresenceStatus {
+        self.remote
+            .read()
+            .await
+            .values()
+            .filter_map(|statuses| statuses.get(&user_id).copied())
+            .fold(PresenceStatus::Offline, PresenceStatus::max)
+    }
+
+    /// The number of users connected to at least one instance
+    pub async fn connected_users_count(&self) -> usize {
+        let mut users_ids: HashSet<i64> = self.local.read().await.keys().copied().collect();
+        for statuses in self.remote.read().await.values() {
+            users_ids.extend(statuses.keys());
+        }
+
+        users_ids.len()
+    }
+
+    /// The status of the users connected to this instance, sent to the other instances
+    pub async fn local_statuses(&self) -> Vec<(i64, PresenceStatus)> {
+        self.local
+            .read()
+            .await
+            .iter()
+            .map(|(user_id, status)| (*user_id, *status))
+            .collect()
+    }
+
+    /// Set the status of an user on this instance
+    /// The status is computed while holding the lock, so two concurrent updates can't save an outdated status
+    /// Returns `None` if it didn't change, else the new local status and the new status on all the instances if it changed
+    pub async fn update_local(
+        &self,
+        user_id: i64,
+        status: impl Future<Output = PresenceStatus>,
+    ) -> Option<(PresenceStatus, Option<PresenceStatus>)> {
+        let mut local = self.local.write().await;
+        let status = status.await;
+        let previous = if status == PresenceStatus::Offline {
+            local.remove(&user_id)
+        } else {
+            local.insert(user_id, status)
+        }
+        .unwrap_or(PresenceStatus::Offline);
+
+        if previous == status {
+            return None;
+        }
+
+        let remote_status = self.remote_status(user_id).await;
+        let current = status.max(remote_status);
+        Some((
+            status,
+            (current != previous.max(remote_status)).then_some(current),
+        ))
+    }
+
+    /// Set the status of an user on another instance
+    /// Returns the new status of the user if it changed
+    pub async fn set_remote(
+        &self,
+        instance_id: u64,
+        user_id: i64,
+        status: PresenceStatus,
+    ) -> Option<PresenceStatus> {
+        let previous = self.status(user_id).await;
+
+        let mut remote = self.remote.write().await;
+        let statuses = remote.entry(instance_id).or_default();
+        if status == PresenceStatus::Offline {
+            statuses.remove(&user_id);
+        } else {
+            statuses.insert(user_id, status);
+        }
+        drop(remote);
+
+        let current = self.status(user_id).await;
+        (current != previous).then_some(current)
+    }
+
+    /// Replace the statuses of the users connected to another instance, an empty list forgets the instance
+    /// Returns the users whose status changed, with their new status
+    pub async fn set_remote_snapshot(
+        &self,
+        instance_id: u64,
+        statuses: Vec<(i64, PresenceStatus)>,
+    ) -> Vec<(i64, PresenceStatus)> {
+        let statuses: HashMap<i64, PresenceStatus> = statuses.into_iter().collect();
+
+        let mut users_ids: Vec<i64> = statuses.keys().copied().collect();
+        if let Some(previous_statuses) = self.remote.read().await.get(&instance_id) {
+            users_ids.extend(previous_statuses.keys());
+        }
+        users_ids.sort_unstable();
+        users_ids.dedup();
+
+        let mut previous = Vec::with_capacity(users_ids.len());
+        for user_id in &users_ids {
+            previous.push(self.status(*user_id).await);
+        }
+
+        if statuses.is_empty() {
+            self.remote.write().await.remove(&instance_id);
+        } else {
+            self.remote.write().await.insert(instance_id, statuses);
+        }
+
+        let mut changes = Vec::new();
+        for (user_id, previous) in users_ids.into_iter().zip(previous) {
+            let current = self.status(user_id).await;
+            if current != previous {
+                changes.push((user_id, current));
+            }
+        }
+
+        changes
+    }
+
+    /// Save when an user was last connected to this instance
+    pub async fn save_last_seen(&self, user_id: i64) {
+        if let Err(e) = sqlx::query!(
+            "UPDATE account SET last_seen_at = NOW() WHERE id = $1",
+            user_id
+        )
+        .execute(&self.pool)
+        .await
+        {
+            warn!("Error saving when {user_id} was last seen : {e}");
+        }
+    }
+
+    /// Whether an user hides its presence from the other users
+    pub async fn is_hidden(&self, user_id: i64) -> bool {
+        sqlx::query_scalar!("SELECT presence_hidden FROM account WHERE id = $1", user_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| warn!("Error checking if {user_id} hides its presence : {e}"))
+            .ok()
+            .flatten()
+            .unwrap_or(true)
+    }
+
+    /// Whether an user can receive the presence changes of another user
+    /// Only the followers of an user that doesn't hide its presence can
+    pub async fn can_follow(&self, follower_id: Option<i64>, user_id: i64) -> bool {
+        let Some(follower_id) = follower_id else {
+            return false;
+        };
+
+        sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM follow WHERE follower_id = $1 AND followed_id = $2) AND NOT presence_hidden AS "allowed!" FROM account WHERE id = $2"#,
+            follower_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| warn!("Error checking if {follower_id} can follow the presence of {user_id} : {e}"))
+        .ok()
+        .flatten()
+        .unwrap_or(false)
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+use tracing::{info, warn};
+
+use crate::AppState;
+
+use super::{
+    app_error::AppError,
+    post::{announce_new_post, get_notification_post},
+    real_time_event_management::{EventTracker, Users},
+};
+
+struct DuePost {
+    id: i64,
+    author_id: i64,
+    title: String,
+    content: String,
+}
+
+/// Publish the scheduled posts whose publication date has passed, then notify the users concerned
+pub async fn publish_scheduled_posts(
+    app_state: &AppState,
+    users: &Users,
+    event_tracker: &EventTracker,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(30));
+    loop {
+        interval.tick().await;
+        if let Ok(count) = publish_due_posts(&app_state.pool, users, event_tracker).await {
+            if count > 0 {
+                info!("Published {count} scheduled post.s");
+            }
+        }
+    }
+}
+
+async fn publish_due_posts(
+    pool: &PgPool,
+    users: &Users,
+    event_tracker: &EventTracker,
+) -> Result<usize, AppError> {
+    let posts = sqlx::query_as!(
+        DuePost,
+        "UPDATE post SET published = TRUE, publish_at = NULL, created_at = NOW(), updated_at = NOW() WHERE NOT published AND publish_at <= NOW() RETURNING id, author_id, title, content"
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        warn!("Error publishing scheduled posts : {e}");
+        AppError::internal_server_error()
+    })?;
+
+    for due_post in &posts {
+        //A failed announcement shouldn't prevent the other posts from being announced
+        let Ok(Some(post)) =
+            get_notification_post(pool, due_post.id, Some(due_post.author_id)).await
+        else {
+            warn!("Cannot announce scheduled post {}", due_post.id);
+            continue;
+        };
+
+        if announce_new_post(
+            pool,
+            users,
+            event_tracker,
+            &post,
+            &due_post.title,
+            &due_post.content,
+        )
+        .await
+        .is_err()
+        {
+            warn!("Cannot announce scheduled post {}", due_post.id);
+        }
+    }
+
+    Ok(posts.len())
+}
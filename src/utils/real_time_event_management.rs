@@ -1,54 +1,286 @@
 use std::{
-    collections::{hash_map::Entry, HashMap, HashSet},
-    sync::{atomic::Ordering, Arc},
+    borrow::Cow,
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
-use axum::extract::ws::{Message, WebSocket};
-use axum::Error;
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
 use futures_util::{
     stream::{FuturesUnordered, SplitSink},
     SinkExt, StreamExt,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tokio::sync::RwLock;
+use sqlx::PgPool;
+use tokio::{
+    sync::{
+        mpsc::{self, error::TrySendError},
+        oneshot, watch, Notify, RwLock,
+    },
+    time::{interval, sleep, Instant},
+};
 use tracing::{info, warn};
 
-use crate::{models::post::NotificationPost, CONNECTED_USERS_COUNT};
+use crate::{
+    models::{
+        comment::PublicComment,
+        poll::PollResults,
+        post::{NotificationPost, PostVisibility, PublicPostAuthor},
+        presence::PresenceStatus,
+        reaction::ReactionKind,
+    },
+    utils::{
+        event_bus::{BusMessage, EventBus},
+        post::get_notification_post,
+        presence::Presence,
+        ws_protocol::{legacy_subscription_event, parse_legacy_subscription, ProtocolVersion},
+    },
+};
 
 pub type Users = Arc<RwLock<HashMap<i64, Vec<Arc<RwLock<UserConnection>>>>>>;
 pub const SUBSCRIBE_TO_EVENT_ACTION_NAME: &str = "subscribe_to_event";
 pub const UNSUBSCRIBE_TO_EVENT_ACTION_NAME: &str = "unsubscribe_from_event";
 pub const NEW_POST_NOTIFICATION_EVENT_NAME: &str = "new_post_notification";
+pub const NEW_POST_WITH_TAG_EVENT_NAME: &str = "new_post_with_tag";
+pub const MENTION_EVENT_NAME: &str = "mention";
+pub const REACTION_EVENT_NAME: &str = "reaction";
+pub const POST_REACTIONS_UPDATE_EVENT_NAME: &str = "post_reactions_update";
+pub const NEW_COMMENT_EVENT_NAME: &str = "new_comment";
+pub const COMMENT_EVENT_NAME: &str = "comment";
+pub const POLL_UPDATED_EVENT_NAME: &str = "poll_updated";
+pub const POLL_CLOSED_EVENT_NAME: &str = "poll_closed";
+pub const FOLLOW_EVENT_NAME: &str = "follow";
+pub const USER_POST_EVENT_NAME: &str = "user_post";
+pub const AUTHENTICATED_EVENT_NAME: &str = "authenticated";
+pub const REPLAYED_EVENT_NAME: &str = "replayed";
+pub const PRESENCE_CHANGED_EVENT_NAME: &str = "presence_changed";
 pub const CONNECTED_USERS_COUNT_UPDATE_EVENT_NAME: &str = "connected_users_count_update";
 pub const ERROR_EVENT_NAME: &str = "error";
 
+/// How the server checks that the connections are still alive
+#[derive(Clone, Copy)]
+pub struct HeartbeatConfig {
+    /// The time between two ping frames
+    pub ping_interval: Duration,
+    /// The time a connection has to answer a ping before being disconnected
+    pub pong_timeout: Duration,
+}
+
+/// The number of messages that can wait to be sent to a connection before it is considered too slow
+pub const MAX_QUEUED_MESSAGES: usize = 256;
+/// Close code sent to the connections that don't read their messages fast enough
+pub const SLOW_CONSUMER_CLOSE_CODE: u16 = 4003;
+/// The number of connections closed because they were too slow since the server started
+pub static SLOW_CONSUMERS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
 /// A struct that represents an user connection
-/// Includes the events the connection is subscribed to and the sender
+/// Includes the events the connection is subscribed to and the queue of the messages to send
+/// The messages are sent by a dedicated task, so a slow client never blocks the others
 pub struct UserConnection {
+    /// The id of the authenticated user, `None` for a not connected user
+    pub user_id: Option<i64>,
+    /// Set by the client when the user isn't active anymore, the user is online if one of its connections isn't away
+    pub away: bool,
     subscribed_events: HashSet<RealTimeEvent>,
-    sender: SplitSink<WebSocket, Message>,
+    queue: mpsc::Sender<Message>,
+    /// Set when the connection must be closed, the close frame skips the queued messages
+    close: watch::Sender<Option<CloseFrame<'static>>>,
 }
 
 impl UserConnection {
-    /// Create a new `UserConnection` struct, with no subscribed events
-    pub fn new(sender: SplitSink<WebSocket, Message>) -> Self {
-        Self {
-            subscribed_events: HashSet::default(),
+    /// Create a new `UserConnection` struct, with no subscribed events, and start its writer task
+    /// `replay` gives the events missed by a reconnecting client, they are sent before the queued messages
+    pub fn new(
+        sender: SplitSink<WebSocket, Message>,
+        user_id: Option<i64>,
+        replay: Option<oneshot::Receiver<Vec<String>>>,
+    ) -> Self {
+        let (connection, queue_receiver) = Self::new_queued(user_id);
+
+        tokio::spawn(write_messages(
             sender,
+            queue_receiver,
+            connection.closed(),
+            replay,
+        ));
+
+        connection
+    }
+
+    /// Create a new `UserConnection` struct whose messages are read from the returned queue
+    /// Used by the SSE connections, that don't have a socket to write to
+    pub fn new_queued(user_id: Option<i64>) -> (Self, mpsc::Receiver<Message>) {
+        let (queue, queue_receiver) = mpsc::channel(MAX_QUEUED_MESSAGES);
+        let (close, _) = watch::channel(None);
+
+        let connection = Self {
+            user_id,
+            away: false,
+            subscribed_events: HashSet::default(),
+            queue,
+            close,
+        };
+
+        (connection, queue_receiver)
+    }
+
+    /// Queue a message without waiting, the connection is closed if its queue is full
+    fn send(&self, message: Message) {
+        //If the writer task stopped, the connection is already closed
+        if let Err(TrySendError::Full(_)) = self.queue.try_send(message) {
+            if self.close.borrow().is_none() {
+                warn!("Closing a connection that has {MAX_QUEUED_MESSAGES} queued messages");
+                SLOW_CONSUMERS_COUNT.fetch_add(1, Ordering::Relaxed);
+                self.close.send_replace(Some(CloseFrame {
+                    code: SLOW_CONSUMER_CLOSE_CODE,
+                    reason: Cow::Borrowed("Connexion trop lente."),
+                }));
+            }
         }
     }
 
-    pub async fn send_text_event(&mut self, event: String) -> Result<(), Error> {
-        self.sender.send(Message::Text(event)).await
+    pub fn send_text_event(&self, event: String) {
+        self.send(Message::Text(event));
+    }
+
+    pub fn is_subscribed(&self, event: &RealTimeEvent) -> bool {
+        self.subscribed_events.contains(event)
+    }
+
+    pub fn send_ping(&self) {
+        self.send(Message::Ping(Vec::new()));
+    }
+
+    /// The number of messages waiting to be sent
+    pub fn queue_depth(&self) -> usize {
+        self.queue.max_capacity() - self.queue.capacity()
+    }
+
+    /// Changes when the server closes the connection
+    pub fn closed(&self) -> watch::Receiver<Option<CloseFrame<'static>>> {
+        self.close.subscribe()
     }
 }
 
+/// Send the queued messages of a connection until it is closed
+async fn write_messages(
+    mut sender: SplitSink<WebSocket, Message>,
+    mut queue: mpsc::Receiver<Message>,
+    mut close: watch::Receiver<Option<CloseFrame<'static>>>,
+    replay: Option<oneshot::Receiver<Vec<String>>>,
+) {
+    //Nothing is replayed if the missed events can't be loaded
+    if let Some(replay) = replay {
+        for event in replay.await.unwrap_or_default() {
+            if let Err(e) = sender.send(Message::Text(event)).await {
+                warn!("Error sending a missed event : {e}");
+                return;
+            }
+        }
+    }
+
+    loop {
+        tokio::select! {
+            biased;
+            Ok(()) = close.changed() => {
+                let frame = close.borrow().clone();
+                if let Err(e) = sender.send(Message::Close(frame)).await {
+                    warn!("Error closing a connection : {e}");
+                }
+                break;
+            }
+            message = queue.recv() => {
+                //The connection has been dropped and all its messages have been sent
+                let Some(message) = message else {
+                    break;
+                };
+                if let Err(e) = sender.send(message).await {
+                    warn!("Error sending a message : {e}");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// The state of the queues of the connections
+#[derive(Serialize)]
+pub struct QueueMetrics {
+    pub connections: usize,
+    pub queued_messages: usize,
+    pub max_queue_depth: usize,
+    pub slow_consumers_closed: usize,
+}
+
+/// Get the state of the queues of all the connections
+pub async fn get_queue_metrics(users: &Users) -> QueueMetrics {
+    let mut metrics = QueueMetrics {
+        connections: 0,
+        queued_messages: 0,
+        max_queue_depth: 0,
+        slow_consumers_closed: SLOW_CONSUMERS_COUNT.load(Ordering::Relaxed),
+    };
+
+    for connection in users.read().await.values().flatten() {
+        let depth = connection.read().await.queue_depth();
+        metrics.connections += 1;
+        metrics.queued_messages += depth;
+        metrics.max_queue_depth = metrics.max_queue_depth.max(depth);
+    }
+
+    metrics
+}
+
 /// Struct that represents all the possible events that a connection can be subscribed to
-#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+#[derive(Eq, PartialEq, Hash, Clone, Debug, Serialize, Deserialize)]
 pub enum RealTimeEvent {
-    NewPostNotification { followed_user_id: i64 },
+    NewPostWithTag {
+        tag: String,
+    },
     ConnectedUsersCountUpdate,
+    PostReactionsUpdate {
+        post_id: i64,
+    },
+    NewComment {
+        post_id: i64,
+    },
+    PollUpdated {
+        post_id: i64,
+    },
+    /// The new public posts of an user, anyone can subscribe to it
+    UserPosts {
+        user_id: i64,
+    },
+    /// Notifications sent to a specific user, every connection of an authenticated user is subscribed to it
+    UserNotification {
+        user_id: i64,
+    },
+    /// Changes of the presence of an user, only its followers can subscribe to it
+    PresenceChanged {
+        user_id: i64,
+    },
+}
+
+impl RealTimeEvent {
+    /// Whether the connections subscribed to this event can receive a post with the given visibility
+    pub fn can_receive_post(&self, visibility: PostVisibility) -> bool {
+        match self {
+            //Anyone can subscribe to these events
+            RealTimeEvent::NewPostWithTag { .. }
+            | RealTimeEvent::UserPosts { .. }
+            | RealTimeEvent::ConnectedUsersCountUpdate => visibility == PostVisibility::Public,
+            //Checked when subscribing to the events of a post, and by the sender for the notifications
+            RealTimeEvent::PostReactionsUpdate { .. }
+            | RealTimeEvent::NewComment { .. }
+            | RealTimeEvent::PollUpdated { .. }
+            | RealTimeEvent::UserNotification { .. } => true,
+            RealTimeEvent::PresenceChanged { .. } => false,
+        }
+    }
 }
 
 pub type Events = Arc<RwLock<HashMap<RealTimeEvent, Vec<Arc<RwLock<UserConnection>>>>>>;
@@ -59,13 +291,42 @@ pub struct ClientEvent {
     content: serde_json::Value,
 }
 
+/// When the last presence snapshot of each other instance was received
+type RemoteInstances = Arc<RwLock<HashMap<u64, Instant>>>;
+
+/// The time between two presence snapshots of an instance
+const PRESENCE_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(30);
+/// An instance that doesn't send its presence snapshot for this long is considered stopped
+const PRESENCE_SNAPSHOT_EXPIRATION: Duration = Duration::from_secs(90);
+
 /// Structs that stores all the connections subscribed to all events
-#[derive(Default, Clone)]
+/// The events are also sent to the other instances of the API with the event bus
+#[derive(Clone)]
 pub struct EventTracker {
     events: Events,
+    pool: PgPool,
+    bus: Arc<dyn EventBus>,
+    remote_instances: RemoteInstances,
+    presence: Presence,
+    /// Woken up when the number of connected users changes, the changes are sent together
+    users_count_changed: Arc<Notify>,
+    /// The minimum time between two updates of the number of connected users
+    users_count_interval: Duration,
 }
 
 impl EventTracker {
+    pub fn new(bus: Arc<dyn EventBus>, pool: PgPool, users_count_interval: Duration) -> Self {
+        EventTracker {
+            events: Events::default(),
+            bus,
+            remote_instances: RemoteInstances::default(),
+            presence: Presence::new(pool.clone()),
+            pool,
+            users_count_changed: Arc::default(),
+            users_count_interval,
+        }
+    }
+
     pub async fn subscribe(
         &self,
         event_type: RealTimeEvent,
@@ -134,28 +395,245 @@ impl EventTracker {
         warn!("User not subscribed to event {event_type:?}.");
     }
 
+    /// Send an event to the subscribers of all the instances
     pub async fn notify(&self, event_type: RealTimeEvent, content: String) {
-        if let Some(connections) = self.events.read().await.get(&event_type) {
-            let f = FuturesUnordered::new();
+        self.deliver(&event_type, &content).await;
+        self.bus
+            .publish(BusMessage::Event {
+                event: event_type,
+                content,
+            })
+            .await;
+    }
+
+    /// Send an event to the subscribers connected to this instance
+    async fn deliver(&self, event_type: &RealTimeEvent, content: &str) {
+        //Don't hold the lock of the events while queuing the messages
+        let Some(connections) = self.events.read().await.get(event_type).cloned() else {
+            return;
+        };
+
+        for connection in connections {
+            connection.read().await.send_text_event(content.to_string());
+        }
+    }
+
+    /// Send an event to the connections of the given users on all the instances
+    pub async fn notify_users(
+        &self,
+        users: &Users,
+        recipients: Vec<Recipient>,
+        event: serde_json::Value,
+    ) {
+        send_to_users(users, &recipients, &event).await;
+        self.bus
+            .publish(BusMessage::Users { recipients, event })
+            .await;
+    }
+
+    /// The presence of an user on all the instances
+    pub async fn presence_status(&self, user_id: i64) -> PresenceStatus {
+        self.presence.status(user_id).await
+    }
+
+    /// Whether a connection can subscribe to an event
+    /// The events of a post need to see the post, and the presence changes of an user to follow it
+    pub async fn can_subscribe(&self, user_id: Option<i64>, event: &RealTimeEvent) -> bool {
+        match event {
+            RealTimeEvent::PostReactionsUpdate { post_id }
+            | RealTimeEvent::NewComment { post_id }
+            | RealTimeEvent::PollUpdated { post_id } => {
+                get_notification_post(&self.pool, *post_id, user_id)
+                    .await
+                    .is_ok_and(|post| post.is_some())
+            }
+            RealTimeEvent::PresenceChanged {
+                user_id: followed_id,
+            } => self.presence.can_follow(user_id, *followed_id).await,
+            _ => true,
+        }
+    }
+
+    /// Update the presence of an user after a change of its connections to this instance
+    /// The connections of an authenticated user are all subscribed to its notifications
+    pub async fn update_presence(&self, user_id: i64) {
+        let status = async {
+            let connections = self
+                .events
+                .read()
+                .await
+                .get(&RealTimeEvent::UserNotification { user_id })
+                .cloned()
+                .unwrap_or_default();
 
+            let mut status = PresenceStatus::Offline;
             for connection in connections {
-                f.push({
-                    let content = content.clone();
-                    async move {
-                        if let Err(e) = connection
-                            .write()
-                            .await
-                            .sender
-                            .send(Message::Text(content))
-                            .await
-                        {
-                            warn!("{e}");
-                        }
-                    }
+                status = status.max(if connection.read().await.away {
+                    PresenceStatus::Away
+                } else {
+                    PresenceStatus::Online
                 });
             }
+            status
+        };
+
+        let Some((status, change)) = self.presence.update_local(user_id, status).await else {
+            return;
+        };
 
-            f.collect::<Vec<()>>().await;
+        if status == PresenceStatus::Offline {
+            self.presence.save_last_seen(user_id).await;
+        }
+
+        self.bus
+            .publish(BusMessage::Presence { user_id, status })
+            .await;
+
+        if let Some(current) = change {
+            self.presence_changed(user_id, current).await;
+        }
+    }
+
+    /// Send a change of the presence of an user on all the instances to the local subscribers
+    /// An user going online or offline also changes the number of connected users
+    async fn presence_changed(&self, user_id: i64, status: PresenceStatus) {
+        self.users_count_changed.notify_one();
+        self.deliver_presence(user_id, status).await;
+    }
+
+    /// Send a presence change to the local subscribers, unless the user hides its presence
+    async fn deliver_presence(&self, user_id: i64, status: PresenceStatus) {
+        let event_type = RealTimeEvent::PresenceChanged { user_id };
+        //Most users have no subscriber, don't query the database for them
+        if !self.events.read().await.contains_key(&event_type) {
+            return;
+        }
+        if self.presence.is_hidden(user_id).await {
+            return;
+        }
+
+        let event = WsEvent::new_presence_changed_event(user_id, status).to_string();
+        self.deliver(&event_type, &event).await;
+    }
+
+    /// Send the current number of connected users to the local subscribers
+    /// The changes are coalesced, so a wave of connections sends one update per interval instead of one per connection
+    async fn broadcast_users_count(&self) {
+        let mut delivered_count = None;
+        loop {
+            self.users_count_changed.notified().await;
+
+            let count = self.presence.connected_users_count().await;
+            if delivered_count != Some(count) {
+                let event = WsEvent::new_connected_users_count_update_event(count).to_string();
+                self.deliver(&RealTimeEvent::ConnectedUsersCountUpdate, &event)
+                    .await;
+                delivered_count = Some(count);
+            }
+
+            //The changes made meanwhile are sent after the interval
+            sleep(self.users_count_interval).await;
+        }
+    }
+
+    /// Deliver the messages of the other instances to the connections of this instance
+    /// Also sends the presence of the local users at regular intervals, and the number of connected users when it changes
+    pub async fn relay_bus_messages(&self, users: &Users) {
+        tokio::join!(
+            self.receive_bus_messages(users),
+            self.publish_presence_snapshot(),
+            self.broadcast_users_count()
+        );
+    }
+
+    async fn receive_bus_messages(&self, users: &Users) {
+        loop {
+            let received = self.bus.receive().await;
+            match received.message {
+                BusMessage::Event { event, content } => self.deliver(&event, &content).await,
+                BusMessage::Users { recipients, event } => {
+                    send_to_users(users, &recipients, &event).await;
+                }
+                BusMessage::Presence { user_id, status } => {
+                    let change = self
+                        .presence
+                        .set_remote(received.instance_id, user_id, status)
+                        .await;
+                    if let Some(status) = change {
+                        self.presence_changed(user_id, status).await;
+                    }
+                }
+                BusMessage::PresenceSnapshot { statuses } => {
+                    self.remote_instances
+                        .write()
+                        .await
+                        .insert(received.instance_id, Instant::now());
+                    let changes = self
+                        .presence
+                        .set_remote_snapshot(received.instance_id, statuses)
+                        .await;
+                    for (user_id, status) in changes {
+                        self.presence_changed(user_id, status).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Send the presence of the users connected to this instance to the others
+    /// Also forgets the stopped instances
+    async fn publish_presence_snapshot(&self) {
+        let mut interval = interval(PRESENCE_SNAPSHOT_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            //The instances started after a change of presence only know it from here
+            let statuses = self.presence.local_statuses().await;
+            self.bus
+                .publish(BusMessage::PresenceSnapshot { statuses })
+                .await;
+
+            let mut remote_instances = self.remote_instances.write().await;
+            let mut expired_instances = Vec::new();
+            remote_instances.retain(|instance_id, received_at| {
+                let expired = received_at.elapsed() >= PRESENCE_SNAPSHOT_EXPIRATION;
+                if expired {
+                    expired_instances.push(*instance_id);
+                }
+                !expired
+            });
+            drop(remote_instances);
+
+            if expired_instances.is_empty() {
+                continue;
+            }
+
+            warn!(
+                "{} instance.s stopped sending their presence snapshot",
+                expired_instances.len()
+            );
+
+            for instance_id in expired_instances {
+                let changes = self
+                    .presence
+                    .set_remote_snapshot(instance_id, Vec::new())
+                    .await;
+                for (user_id, status) in changes {
+                    self.presence_changed(user_id, status).await;
+                }
+            }
+        }
+    }
+
+    /// Notify the subscribers of an event about a post, only if they can see it
+    pub async fn notify_post(
+        &self,
+        event_type: RealTimeEvent,
+        visibility: PostVisibility,
+        content: String,
+    ) {
+        if event_type.can_receive_post(visibility) {
+            self.notify(event_type, content).await;
         }
     }
 
@@ -172,22 +650,17 @@ impl EventTracker {
             }
         };
 
-        let content = client_event.content;
-
-        let Some(event_name) = content.as_str() else {
-            return Err(
-                "Le champs `event` à l'intérieur de `content` doit être une chaîne de caractères."
-                    .to_string(),
-            );
-        };
-
-        let event = match event_name {
-            CONNECTED_USERS_COUNT_UPDATE_EVENT_NAME => RealTimeEvent::ConnectedUsersCountUpdate,
-            event => return Err(format!("L'event `{event}` n'existe pas.")),
-        };
+        let subscription = parse_legacy_subscription(client_event.content)?;
+        let event = legacy_subscription_event(subscription)?;
 
         match client_event.action.as_str() {
-            SUBSCRIBE_TO_EVENT_ACTION_NAME => self.subscribe(event, sender).await,
+            SUBSCRIBE_TO_EVENT_ACTION_NAME => {
+                let user_id = sender.read().await.user_id;
+                if !self.can_subscribe(user_id, &event).await {
+                    return Err("Tu ne peux pas t'abonner à cet événement.".to_string());
+                }
+                self.subscribe(event, sender).await;
+            }
             UNSUBSCRIBE_TO_EVENT_ACTION_NAME => self.unsubscribe(event, sender).await,
             action => return Err(format!("L'action `{action}` n'existe pas.")),
         }
@@ -209,14 +682,13 @@ impl EventTracker {
             }
             Entry::Vacant(entry) => {
                 entry.insert(vec![user]);
-                if id > -1 {
-                    let count = CONNECTED_USERS_COUNT.fetch_add(1, Ordering::Relaxed);
-                    let event = WsEvent::new_connected_users_count_update_event(count).to_string();
-                    self.notify(RealTimeEvent::ConnectedUsersCountUpdate, event)
-                        .await;
-                }
             }
         }
+
+        //A new connection can make an away user online
+        if id > -1 {
+            self.update_presence(id).await;
+        }
     }
 
     pub async fn remove_from_users(
@@ -234,12 +706,6 @@ impl EventTracker {
             if subscribers.len() == 1 {
                 //TODO Not sure if it's useful
                 if Arc::ptr_eq(&subscribers[0], &user) {
-                    //If the user was connected, decrement the connected users count
-                    let user_count = CONNECTED_USERS_COUNT.fetch_sub(1, Ordering::Relaxed);
-                    let event =
-                        WsEvent::new_connected_users_count_update_event(user_count).to_string();
-                    self.notify(RealTimeEvent::ConnectedUsersCountUpdate, event)
-                        .await;
                     entry.remove_entry();
                     return;
                 }
@@ -275,10 +741,43 @@ impl EventTracker {
             self.remove_from_users(id, users, user)
         );
 
+        if id > -1 {
+            self.update_presence(id).await;
+        }
+
         info!("User {} disconnected", id);
     }
 }
 
+/// An user an event is sent to, with the id of the notification the event is stored as
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct Recipient {
+    pub user_id: i64,
+    pub event_id: i64,
+}
+
+/// Send an event to the connections of the given users, the users that aren't connected are skipped
+async fn send_to_users(users: &Users, recipients: &[Recipient], event: &serde_json::Value) {
+    //Don't hold the lock of the users while queuing the messages
+    let connections: Vec<(i64, Vec<Arc<RwLock<UserConnection>>>)> = {
+        let users = users.read().await;
+        recipients
+            .iter()
+            .filter_map(|recipient| {
+                let connections = users.get(&recipient.user_id)?;
+                Some((recipient.event_id, connections.clone()))
+            })
+            .collect()
+    };
+
+    for (event_id, connections) in connections {
+        let content = WsEvent::with_id(event, event_id).to_string();
+        for connection in connections {
+            connection.read().await.send_text_event(content.clone());
+        }
+    }
+}
+
 /// A struct that represents a WS event sent by the server to the user
 pub struct WsEvent;
 
@@ -290,6 +789,98 @@ impl WsEvent {
         })
     }
 
+    pub fn new_new_post_with_tag_event(tag: &str, post: &NotificationPost) -> serde_json::Value {
+        json! ({
+            "event": NEW_POST_WITH_TAG_EVENT_NAME,
+            "content": {
+                "tag": tag,
+                "post": post,
+            },
+        })
+    }
+
+    pub fn new_mention_event(post: &NotificationPost) -> serde_json::Value {
+        json! ({
+            "event": MENTION_EVENT_NAME,
+            "content": post,
+        })
+    }
+
+    pub fn new_follow_event(follower: &PublicPostAuthor) -> serde_json::Value {
+        json! ({
+            "event": FOLLOW_EVENT_NAME,
+            "content": follower,
+        })
+    }
+
+    pub fn new_reaction_event(
+        post: &NotificationPost,
+        kind: ReactionKind,
+        user: &PublicPostAuthor,
+    ) -> serde_json::Value {
+        json! ({
+            "event": REACTION_EVENT_NAME,
+            "content": {
+                "post": post,
+                "kind": kind,
+                "user": user,
+            },
+        })
+    }
+
+    pub fn new_post_reactions_update_event(
+        post_id: i64,
+        reactions: &BTreeMap<ReactionKind, i64>,
+    ) -> serde_json::Value {
+        json! ({
+            "event": POST_REACTIONS_UPDATE_EVENT_NAME,
+            "content": {
+                "post_id": post_id,
+                "reactions": reactions,
+            },
+        })
+    }
+
+    pub fn new_new_comment_event(comment: &PublicComment) -> serde_json::Value {
+        json! ({
+            "event": NEW_COMMENT_EVENT_NAME,
+            "content": comment,
+        })
+    }
+
+    pub fn new_comment_event(
+        post: &NotificationPost,
+        comment: &PublicComment,
+    ) -> serde_json::Value {
+        json! ({
+            "event": COMMENT_EVENT_NAME,
+            "content": {
+                "post": post,
+                "comment": comment,
+            },
+        })
+    }
+
+    pub fn new_poll_updated_event(results: &PollResults) -> serde_json::Value {
+        json! ({
+            "event": POLL_UPDATED_EVENT_NAME,
+            "content": results,
+        })
+    }
+
+    pub fn new_poll_closed_event(
+        post: &NotificationPost,
+        results: &PollResults,
+    ) -> serde_json::Value {
+        json! ({
+            "event": POLL_CLOSED_EVENT_NAME,
+            "content": {
+                "post": post,
+                "results": results,
+            },
+        })
+    }
+
     pub fn new_connected_users_count_update_event(count: usize) -> serde_json::Value {
         json! ({
             "event": CONNECTED_USERS_COUNT_UPDATE_EVENT_NAME,
@@ -297,6 +888,54 @@ impl WsEvent {
         })
     }
 
+    pub fn new_user_post_event(post: &NotificationPost) -> serde_json::Value {
+        json! ({
+            "event": USER_POST_EVENT_NAME,
+            "content": post,
+        })
+    }
+
+    pub fn new_presence_changed_event(user_id: i64, status: PresenceStatus) -> serde_json::Value {
+        json! ({
+            "event": PRESENCE_CHANGED_EVENT_NAME,
+            "content": {
+                "user_id": user_id,
+                "status": status,
+            },
+        })
+    }
+
+    /// Add the id of the notification an event is stored as, so the client can get the events it missed
+    pub fn with_id(event: &serde_json::Value, id: i64) -> serde_json::Value {
+        let mut event = event.clone();
+        event["id"] = json!(id);
+        event
+    }
+
+    pub fn new_replayed_event(count: usize, truncated: bool) -> serde_json::Value {
+        json! ({
+            "event": REPLAYED_EVENT_NAME,
+            "content": {
+                "count": count,
+                "truncated": truncated,
+            },
+        })
+    }
+
+    /// Acknowledge the authentication of a connection, `user_id` is `None` for a not connected user
+    pub fn new_authenticated_event(
+        user_id: Option<i64>,
+        protocol_version: ProtocolVersion,
+    ) -> serde_json::Value {
+        json! ({
+            "event": AUTHENTICATED_EVENT_NAME,
+            "content": {
+                "user_id": user_id,
+                "protocol_version": protocol_version as u8,
+            },
+        })
+    }
+
     pub fn new_error(text: &str) -> serde_json::Value {
         json! ({
             "event": ERROR_EVENT_NAME,
@@ -307,17 +946,9 @@ impl WsEvent {
 
 //TODO use it
 /*pub async fn broadcast_event(users: Users, content: &str) {
-    for (user_id, user) in users.write().await.iter() {
+    for user in users.read().await.values() {
         for connection in user {
-            if let Err(e) = connection
-                .write()
-                .await
-                .sender
-                .send(Message::Text(content.to_string()))
-                .await
-            {
-                warn!("Error sending event to {user_id} : {e}");
-            };
+            connection.read().await.send_text_event(content.to_string());
         }
     }
 }*/
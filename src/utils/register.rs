@@ -50,29 +50,64 @@ pub fn send_html_message(
     Ok(())
 }
 
+/// A rule of the usernames broken by an username
+#[derive(Clone, Copy)]
+pub enum InvalidUsername {
+    WrongSize,
+    NotStartingWithLetter,
+    InvalidCharacter,
+}
+
+impl InvalidUsername {
+    /// The message logged by the server
+    fn log_message(self) -> &'static str {
+        match self {
+            InvalidUsername::WrongSize => "Wrong username size",
+            InvalidUsername::NotStartingWithLetter => "The username has to begin with a letter",
+            InvalidUsername::InvalidCharacter => {
+                "The username has to contain only letters, digits and underscores"
+            }
+        }
+    }
+
+    /// The message sent to the client
+    fn client_message(self) -> &'static str {
+        match self {
+            InvalidUsername::WrongSize => {
+                "Le nom d'utilisateur doit contenir entre 5 et 12 caractères."
+            }
+            InvalidUsername::NotStartingWithLetter => {
+                "Le nom d'utilisateur doit commencer par une lettre."
+            }
+            InvalidUsername::InvalidCharacter => {
+                "Le nom d'utilisateur ne doit contenir que des lettres, des chiffres et des underscores."
+            }
+        }
+    }
+}
+
 pub fn check_username(username: &str) -> Result<(), AppError> {
+    check_username_rules(username).map_err(|error| {
+        warn!("{} : {username}", error.log_message());
+        AppError::new(StatusCode::FORBIDDEN, Some(error.client_message()))
+    })
+}
+
+/// Check the rules an username has to follow, without logging anything
+pub fn check_username_rules(username: &str) -> Result<(), InvalidUsername> {
     if username.len() < 5 || username.len() > 12 {
-        warn!("Wrong username size : {username}");
-        return Err(AppError::new(
-            StatusCode::FORBIDDEN,
-            Some("Le nom d'utilisateur doit contenir entre 5 et 12 caractères."),
-        ));
+        return Err(InvalidUsername::WrongSize);
     }
 
     for (i, c) in username.char_indices() {
         if i == 0 {
             if !c.is_alphabetic() {
-                warn!("The username has to begin with a letter : {username}");
-                return Err(AppError::new(
-                    StatusCode::FORBIDDEN,
-                    Some("Le nom d'utilisateur doit commencer par une lettre."),
-                ));
+                return Err(InvalidUsername::NotStartingWithLetter);
             }
             continue;
         }
         if !c.is_alphanumeric() && c != '_' {
-            warn!("The username has to contain only letters, digits and underscores : {username}");
-            return Err(AppError::new(StatusCode::FORBIDDEN, Some("Le nom d'utilisateur ne doit contenir que des lettres, des chiffres et des underscores.")));
+            return Err(InvalidUsername::InvalidCharacter);
         }
     }
 
@@ -0,0 +1,66 @@
+use std::ops::Range;
+
+pub const MAX_TAG_LENGTH: usize = 50;
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Find the words starting with `prefix` in a text (like `#tags` or `@mentions`)
+/// Returns the byte ranges of the words, without the prefix
+/// A prefix only starts a word at the beginning of the text or after a character that can't be part of a word
+pub fn find_prefixed_words(text: &str, prefix: char) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    let mut previous = None;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if c == prefix && !previous.is_some_and(is_word_char) {
+            let start = i + c.len_utf8();
+            let mut end = start;
+            while let Some(&(j, next)) = chars.peek() {
+                if !is_word_char(next) {
+                    break;
+                }
+                end = j + next.len_utf8();
+                chars.next();
+            }
+
+            if end > start {
+                words.push(start..end);
+            }
+            previous = text[..end].chars().next_back();
+            continue;
+        }
+        previous = Some(c);
+    }
+
+    words
+}
+
+/// Normalize a tag sent by a client (with or without the leading `#`)
+/// Returns `None` if the tag is empty, too long or contains forbidden characters
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.strip_prefix('#').unwrap_or(tag).to_lowercase();
+
+    if tag.is_empty() || tag.chars().count() > MAX_TAG_LENGTH || !tag.chars().all(is_word_char) {
+        return None;
+    }
+
+    Some(tag)
+}
+
+/// Extract the `#tags` of a text, lowercased and without duplicates
+pub fn extract_hashtags(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+
+    for range in find_prefixed_words(text, '#') {
+        if let Some(tag) = normalize_tag(&text[range]) {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+    }
+
+    tags
+}
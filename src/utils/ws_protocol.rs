@@ -0,0 +1,536 @@
+use std::sync::Arc;
+
+use axum::http::HeaderValue;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::RwLock;
+use tracing::warn;
+
+use crate::models::presence::PresenceStatus;
+
+use super::{
+    real_time_event_management::{EventTracker, RealTimeEvent, UserConnection, ERROR_EVENT_NAME},
+    tag::normalize_tag,
+};
+
+/// The untyped `{action, content}` protocol, used when the client doesn't ask for a protocol
+pub const LEGACY_PROTOCOL: &str = "apynext.v1";
+pub const PROTOCOL_V2: &str = "apynext.v2";
+/// The protocols supported in the `Sec-WebSocket-Protocol` header, in decreasing order of preference
+pub const PROTOCOLS: [&str; 2] = [PROTOCOL_V2, LEGACY_PROTOCOL];
+pub const ACK_EVENT_NAME: &str = "ack";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProtocolVersion {
+    V1 = 1,
+    V2 = 2,
+}
+
+impl ProtocolVersion {
+    /// The version of the protocol selected during the upgrade of the connection
+    pub fn from_selected_protocol(protocol: Option<&HeaderValue>) -> Self {
+        match protocol.and_then(|protocol| protocol.to_str().ok()) {
+            Some(PROTOCOL_V2) => ProtocolVersion::V2,
+            _ => ProtocolVersion::V1,
+        }
+    }
+}
+
+/// The documentation of an event or of an error code, generated from its definition
+#[cfg(test)]
+pub struct ItemDoc {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub params: Vec<ParamDoc>,
+}
+
+#[cfg(test)]
+pub struct ParamDoc {
+    pub name: &'static str,
+    pub ty: &'static str,
+    pub description: &'static str,
+}
+
+/// Define the events a client can subscribe to, with their documentation
+macro_rules! subscriptions {
+    ($(
+        $(#[doc = $doc:literal])*
+        $variant:ident $name:literal {
+            $(
+                $(#[doc = $param_doc:literal])*
+                $param:ident : $ty:ty
+            ),* $(,)?
+        }
+    )*) => {
+        /// An event a client can subscribe to, with its parameters
+        #[derive(Deserialize, Debug)]
+        #[serde(tag = "name", deny_unknown_fields)]
+        pub enum Subscription {
+            $(
+                $(#[doc = $doc])*
+                #[serde(rename = $name)]
+                $variant { $($param: $ty),* },
+            )*
+        }
+
+        impl Subscription {
+            /// The names of the parameters of an event, `None` if it doesn't exist
+            pub fn params(name: &str) -> Option<&'static [&'static str]> {
+                match name {
+                    $($name => Some(&[$(stringify!($param)),*]),)*
+                    _ => None,
+                }
+            }
+
+            #[cfg(test)]
+            pub fn docs() -> Vec<ItemDoc> {
+                vec![$(
+                    ItemDoc {
+                        name: $name,
+                        description: concat!($($doc),*),
+                        params: vec![$(
+                            ParamDoc {
+                                name: stringify!($param),
+                                ty: stringify!($ty),
+                                description: concat!($($param_doc),*),
+                            }
+                        ),*],
+                    }
+                ),*]
+            }
+        }
+    };
+}
+
+subscriptions! {
+    /// Changes of the number of connected users
+    ConnectedUsersCountUpdate "connected_users_count_update" {}
+    /// New public posts with a tag
+    NewPostWithTag "new_post_with_tag" {
+        /// The tag, with or without the `#`
+        tag: String,
+    }
+    /// New public posts of an user
+    UserPosts "user_posts" {
+        /// The id of the user
+        user_id: i64,
+    }
+    /// Changes of the reactions of a post
+    PostReactionsUpdate "post_reactions_update" {
+        /// The id of the post
+        post_id: i64,
+    }
+    /// New comments on a post
+    NewComment "new_comment" {
+        /// The id of the post
+        post_id: i64,
+    }
+    /// Votes and end of the poll of a post
+    PollUpdated "poll_updated" {
+        /// The id of the post
+        post_id: i64,
+    }
+    /// Presence changes of an user followed by the authenticated user, unless it hides its presence
+    PresenceChanged "presence_changed" {
+        /// The id of the user
+        user_id: i64,
+    }
+}
+
+impl Subscription {
+    fn into_event(self) -> Result<RealTimeEvent, ProtocolError> {
+        Ok(match self {
+            Subscription::ConnectedUsersCountUpdate {} => RealTimeEvent::ConnectedUsersCountUpdate,
+            Subscription::NewPostWithTag { tag } => {
+                let Some(tag) = normalize_tag(&tag) else {
+                    return Err(ProtocolError::new(
+                        ErrorCode::InvalidParameter,
+                        format!("Invalid tag `{tag}`"),
+                    ));
+                };
+                RealTimeEvent::NewPostWithTag { tag }
+            }
+            Subscription::UserPosts { user_id } => RealTimeEvent::UserPosts { user_id },
+            Subscription::PostReactionsUpdate { post_id } => {
+                RealTimeEvent::PostReactionsUpdate { post_id }
+            }
+            Subscription::NewComment { post_id } => RealTimeEvent::NewComment { post_id },
+            Subscription::PollUpdated { post_id } => RealTimeEvent::PollUpdated { post_id },
+            Subscription::PresenceChanged { user_id } => RealTimeEvent::PresenceChanged { user_id },
+        })
+    }
+}
+
+/// Parse a subscription given as `name` or `name:value`, like `new_comment:42`
+/// The value is the parameter of the event, for the events that have one
+pub fn parse_query_subscription(subscription: &str) -> Option<RealTimeEvent> {
+    let (name, value) = match subscription.split_once(':') {
+        Some((name, value)) => (name, Some(value)),
+        None => (subscription, None),
+    };
+
+    let subscription: Subscription = match (Subscription::params(name)?, value) {
+        ([], None) => serde_json::from_value(json!({ "name": name })).ok()?,
+        //The value is tried as a number then as a string, serde rejects the one that doesn't fit the parameter
+        ([param], Some(value)) => {
+            let number = value.parse::<i64>().ok().map(|number| json!(number));
+            number.into_iter().chain([json!(value)]).find_map(|value| {
+                let mut event = json!({ "name": name });
+                event[*param] = value;
+                serde_json::from_value(event).ok()
+            })?
+        }
+        _ => return None,
+    };
+
+    subscription.into_event().ok()
+}
+
+/// Define the error codes sent to the clients, with their documentation
+macro_rules! error_codes {
+    ($(
+        $(#[doc = $doc:literal])*
+        $variant:ident $name:literal
+    )*) => {
+        #[derive(Serialize, Clone, Copy, Debug)]
+        pub enum ErrorCode {
+            $(
+                $(#[doc = $doc])*
+                #[serde(rename = $name)]
+                $variant,
+            )*
+        }
+
+        impl ErrorCode {
+            #[cfg(test)]
+            pub fn docs() -> Vec<ItemDoc> {
+                vec![$(
+                    ItemDoc {
+                        name: $name,
+                        description: concat!($($doc),*),
+                        params: Vec::new(),
+                    }
+                ),*]
+            }
+        }
+    };
+}
+
+error_codes! {
+    /// The message isn't valid JSON or doesn't match the protocol
+    InvalidMessage "invalid_message"
+    /// A parameter of the event has an invalid value
+    InvalidParameter "invalid_parameter"
+    /// The connection is already subscribed to the event
+    AlreadySubscribed "already_subscribed"
+    /// The connection isn't subscribed to the event
+    NotSubscribed "not_subscribed"
+    /// The authentication token is invalid
+    InvalidToken "invalid_token"
+    /// The connection isn't allowed to do this action
+    Forbidden "forbidden"
+}
+
+/// A request sent by a client
+#[derive(Deserialize)]
+pub struct ClientRequest {
+    /// Echoed in the reply to the request
+    pub request_id: Option<u64>,
+    #[serde(flatten)]
+    pub action: ClientAction,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "action", content = "event", rename_all = "snake_case")]
+pub enum ClientAction {
+    Subscribe(Subscription),
+    Unsubscribe(Subscription),
+    /// Set the presence of the connection, `online` or `away`
+    SetPresence(PresenceStatus),
+}
+
+pub struct ProtocolError {
+    pub code: ErrorCode,
+    /// A description of the error for the developers of the clients
+    pub message: String,
+}
+
+impl ProtocolError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        ProtocolError {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn to_event(&self, request_id: Option<u64>) -> serde_json::Value {
+        json! ({
+            "event": ERROR_EVENT_NAME,
+            "content": {
+                "request_id": request_id,
+                "code": self.code,
+                "message": self.message,
+            },
+        })
+    }
+}
+
+fn new_ack_event(request_id: Option<u64>) -> serde_json::Value {
+    json! ({
+        "event": ACK_EVENT_NAME,
+        "content": {
+            "request_id": request_id,
+        },
+    })
+}
+
+/// Handle a request of a client using the typed protocol, returns the reply to send to the client
+pub async fn handle_client_request(
+    event_tracker: &EventTracker,
+    text: &str,
+    sender: Arc<RwLock<UserConnection>>,
+) -> serde_json::Value {
+    let request: ClientRequest = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(e) => {
+            warn!("Error deserializing WS request : {e}");
+            //Try to echo the request id even if the request is invalid
+            let request_id = serde_json::from_str::<serde_json::Value>(text)
+                .ok()
+                .and_then(|request| request["request_id"].as_u64());
+            return ProtocolError::new(ErrorCode::InvalidMessage, e.to_string())
+                .to_event(request_id);
+        }
+    };
+
+    match apply_action(event_tracker, request.action, sender).await {
+        Ok(()) => new_ack_event(request.request_id),
+        Err(e) => e.to_event(request.request_id),
+    }
+}
+
+async fn apply_action(
+    event_tracker: &EventTracker,
+    action: ClientAction,
+    sender: Arc<RwLock<UserConnection>>,
+) -> Result<(), ProtocolError> {
+    match action {
+        ClientAction::Subscribe(subscription) => {
+            let event = subscription.into_event()?;
+            let user_id = sender.read().await.user_id;
+            if !event_tracker.can_subscribe(user_id, &event).await {
+                return Err(ProtocolError::new(
+                    ErrorCode::Forbidden,
+                    format!("Not allowed to subscribe to {event:?}"),
+                ));
+            }
+            if sender.read().await.is_subscribed(&event) {
+                return Err(ProtocolError::new(
+                    ErrorCode::AlreadySubscribed,
+                    format!("Already subscribed to {event:?}"),
+                ));
+            }
+            event_tracker.subscribe(event, sender).await;
+        }
+        ClientAction::Unsubscribe(subscription) => {
+            let event = subscription.into_event()?;
+            if !sender.read().await.is_subscribed(&event) {
+                return Err(ProtocolError::new(
+                    ErrorCode::NotSubscribed,
+                    format!("Not subscribed to {event:?}"),
+                ));
+            }
+            event_tracker.unsubscribe(event, sender).await;
+        }
+        ClientAction::SetPresence(status) => {
+            if status == PresenceStatus::Offline {
+                return Err(ProtocolError::new(
+                    ErrorCode::InvalidParameter,
+                    "The presence of a connection can only be `online` or `away`",
+                ));
+            }
+
+            let mut connection = sender.write().await;
+            let Some(user_id) = connection.user_id else {
+                return Err(ProtocolError::new(
+                    ErrorCode::Forbidden,
+                    "Only the authenticated connections have a presence",
+                ));
+            };
+            connection.away = status == PresenceStatus::Away;
+            drop(connection);
+
+            event_tracker.update_presence(user_id).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Convert the `content` of an event of the legacy protocol to a subscription
+/// `content` is either the event name or an object containing the event name in `event` and its parameters
+pub fn parse_legacy_subscription(content: serde_json::Value) -> Result<Subscription, String> {
+    let subscription = match content {
+        serde_json::Value::String(event_name) => json!({ "name": event_name }),
+        serde_json::Value::Object(mut params) => {
+            let Some(event_name) = params.remove("event") else {
+                return Err(
+                    "Le champs `event` à l'intérieur de `content` doit être une chaîne de caractères."
+                        .to_string(),
+                );
+            };
+            params.insert("name".to_string(), event_name);
+            serde_json::Value::Object(params)
+        }
+        _ => {
+            return Err(
+                "Le champs `content` doit être une chaîne de caractères ou un objet.".to_string(),
+            )
+        }
+    };
+
+    serde_json::from_value(subscription).map_err(|e| {
+        warn!("Error deserializing legacy WS subscription : {e}");
+        "L'event n'existe pas ou ses paramètres sont invalides.".to_string()
+    })
+}
+
+/// Convert the subscription of a client using the legacy protocol to the event it subscribes to
+pub fn legacy_subscription_event(subscription: Subscription) -> Result<RealTimeEvent, String> {
+    subscription.into_event().map_err(|e| {
+        warn!("Invalid legacy WS subscription : {}", e.message);
+        "Un paramètre de l'event est invalide.".to_string()
+    })
+}
+
+/// Generate the Markdown documentation of the typed protocol, checked by the tests against `doc/websockets_en.md`
+#[cfg(test)]
+fn protocol_markdown() -> String {
+    use std::fmt::Write;
+
+    let mut markdown = format!(
+        "Protocol : `{PROTOCOL_V2}`\n\n\
+        ## Request structure\n\
+        ```json\n\
+        {{\n  \"request_id\": <number>, //optional, echoed in the reply\n  \"action\": \"subscribe\" | \"unsubscribe\" | \"set_presence\",\n  \"event\": {{ \"name\": <string>, ...parameters }}\n}}\n\
+        ```\n\n\
+        Each request is answered with an `{ACK_EVENT_NAME}` event, or an `{ERROR_EVENT_NAME}` event :\n\
+        ```json\n\
+        {{\n  \"event\": \"{ACK_EVENT_NAME}\",\n  \"content\": {{ \"request_id\": <number or null> }}\n}}\n\
+        ```\n\
+        ```json\n\
+        {{\n  \"event\": \"{ERROR_EVENT_NAME}\",\n  \"content\": {{\n    \"request_id\": <number or null>,\n    \"code\": <string>, //error code\n    \"message\": <string> //description of the error, for developers\n  }}\n}}\n\
+        ```\n\n\
+        The `set_presence` action sets the presence of a connection with an account, its `event` is `\"online\"` or `\"away\"`. \
+        An user is `online` if one of its connections is online, `away` if they are all away, and `offline` without connection.\n\n\
+        ## Events\n"
+    );
+
+    for event in Subscription::docs() {
+        let _ = write!(
+            markdown,
+            "\n### `{}`\n{}\n",
+            event.name,
+            event.description.trim()
+        );
+        for param in event.params {
+            let ty = match param.ty {
+                "String" => "string",
+                _ => "number",
+            };
+            let _ = writeln!(
+                markdown,
+                "- `{}` => {ty} -> {}",
+                param.name,
+                param.description.trim()
+            );
+        }
+    }
+
+    markdown.push_str("\n## Error codes\n");
+    for code in ErrorCode::docs() {
+        let _ = writeln!(markdown, "- `{}` : {}", code.name, code.description.trim());
+    }
+
+    markdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_query_subscription, protocol_markdown, ErrorCode, Subscription};
+    use crate::utils::real_time_event_management::RealTimeEvent;
+
+    const DOC_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/doc/websockets_en.md");
+    const FRENCH_DOC_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/doc/websockets.md");
+    const DOC_START_MARKER: &str =
+        "<!-- Generated from src/utils/ws_protocol.rs, do not edit -->\n";
+    const DOC_END_MARKER: &str = "<!-- End of the generated documentation -->\n";
+
+    /// The generated section of `doc/websockets_en.md` matches the types of the protocol
+    /// `UPDATE_WS_DOCS=1 cargo test` writes the new documentation instead of failing
+    #[test]
+    fn generated_docs_are_up_to_date() {
+        let doc = std::fs::read_to_string(DOC_PATH).unwrap();
+        let start = doc.find(DOC_START_MARKER).unwrap() + DOC_START_MARKER.len();
+        let end = doc.find(DOC_END_MARKER).unwrap();
+
+        let generated = protocol_markdown();
+        if std::env::var("UPDATE_WS_DOCS").is_ok() {
+            let doc = format!("{}{generated}{}", &doc[..start], &doc[end..]);
+            std::fs::write(DOC_PATH, doc).unwrap();
+            return;
+        }
+
+        assert_eq!(
+            doc[start..end],
+            generated,
+            "The typed protocol changed, run `UPDATE_WS_DOCS=1 cargo test`"
+        );
+    }
+
+    /// The French documentation is written by hand, but it must mention every event and error code
+    #[test]
+    fn french_docs_mention_the_whole_protocol() {
+        let doc = std::fs::read_to_string(FRENCH_DOC_PATH).unwrap();
+        for item in Subscription::docs().into_iter().chain(ErrorCode::docs()) {
+            assert!(
+                doc.contains(&format!("`{}`", item.name)),
+                "`{}` is missing from doc/websockets.md",
+                item.name
+            );
+        }
+    }
+
+    #[test]
+    fn parses_the_query_subscriptions() {
+        assert_eq!(
+            parse_query_subscription("connected_users_count_update"),
+            Some(RealTimeEvent::ConnectedUsersCountUpdate)
+        );
+        assert_eq!(
+            parse_query_subscription("new_comment:42"),
+            Some(RealTimeEvent::NewComment { post_id: 42 })
+        );
+        //A tag made of digits stays a tag
+        assert!(matches!(
+            parse_query_subscription("new_post_with_tag:2024"),
+            Some(RealTimeEvent::NewPostWithTag { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_the_invalid_query_subscriptions() {
+        for subscription in [
+            "unknown",
+            "new_comment",
+            "new_comment:abc",
+            "connected_users_count_update:1",
+        ] {
+            assert_eq!(
+                parse_query_subscription(subscription),
+                None,
+                "{subscription}"
+            );
+        }
+    }
+}